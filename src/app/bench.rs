@@ -0,0 +1,115 @@
+use std::{
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget};
+use tokio::sync::mpsc::channel;
+use tui_term::widget::PseudoTerminal;
+
+use crate::app::tap::{PtyState, Tap, gate::Gate};
+use crate::config::Config;
+
+// How much output goes through, in reads as big as a flood makes them
+const BENCH_BYTES: usize = 256 * 1024 * 1024;
+const BENCH_READ: usize = 64 * 1024;
+const BENCH_ROWS: u16 = 50;
+const BENCH_COLS: u16 = 200;
+
+// `uncl --bench-throughput` floods a tenant sized emulator with log lines
+// while a stand-in UI draws it every frame, then tells how fast the output
+// went through and how long a frame had to wait for the screen
+pub fn run(config: &Config) -> Result<()> {
+    let parser = Arc::new(RwLock::new(vt100::Parser::new(
+        BENCH_ROWS,
+        BENCH_COLS,
+        config.scrollback.tenant,
+    )));
+    let gate = Arc::new(Gate::default());
+    let (replies, _replies) = channel(32);
+    let mut tap = Tap::new(
+        parser.clone(),
+        Arc::new(RwLock::new(PtyState::default())),
+        replies,
+        gate.clone(),
+        config.clipboard.tenant,
+    );
+
+    let done = Arc::new(AtomicBool::new(false));
+    let frame = Duration::from_millis(config.ui.frame_ms);
+    let ui = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let area = Rect::new(0, 0, BENCH_COLS, BENCH_ROWS);
+            let mut buf = Buffer::empty(area);
+            let (mut frames, mut longest) = (0, Duration::ZERO);
+            while !done.load(Ordering::Relaxed) {
+                let asked = Instant::now();
+                {
+                    let _held = gate.hold();
+                    let parser = parser.read().unwrap();
+                    longest = longest.max(asked.elapsed());
+                    PseudoTerminal::new(parser.screen()).render(area, &mut buf);
+                }
+                frames += 1;
+                thread::sleep(frame);
+            }
+            (frames, longest)
+        })
+    };
+
+    let output = log_lines();
+    let started = Instant::now();
+    let mut fed = 0;
+    while fed < BENCH_BYTES {
+        for read in output.chunks(BENCH_READ) {
+            tap.feed(read);
+            fed += read.len();
+        }
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+
+    done.store(true, Ordering::Relaxed);
+    let (frames, longest) = ui.join().map_err(|_| anyhow!("bench UI thread panicked"))?;
+    let mib = fed as f64 / (1024.0 * 1024.0);
+    println!(
+        "{:.0} MiB in {:.2} s, {:.1} MiB/s through the emulator",
+        mib,
+        elapsed,
+        mib / elapsed
+    );
+    println!(
+        "{} frames drawn meanwhile, the longest waited {:.1} ms for the screen",
+        frames,
+        longest.as_secs_f64() * 1000.0
+    );
+    Ok(())
+}
+
+// A megabyte of colored log lines, the longer ones wrap
+fn log_lines() -> Vec<u8> {
+    let levels = [
+        "\x1b[32mINFO\x1b[0m",
+        "\x1b[33mWARN\x1b[0m",
+        "\x1b[1;31mERROR\x1b[0m",
+    ];
+    let mut output = Vec::new();
+    let mut n = 0;
+    while output.len() < 1024 * 1024 {
+        let line = format!(
+            "{} {:>8} worker-{:<2} {}\r\n",
+            levels[n % levels.len()],
+            n,
+            n % 16,
+            "lorem ipsum dolor sit amet ".repeat(n % 12 + 1)
+        );
+        output.extend_from_slice(line.as_bytes());
+        n += 1;
+    }
+    output
+}
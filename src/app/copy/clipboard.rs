@@ -0,0 +1,36 @@
+use std::{
+    io::{self, Write},
+    sync::Mutex,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+// Base64 of the last thing put on the host clipboard, what OSC 52 reads from
+// inside are answered with
+static LAST: Mutex<String> = Mutex::new(String::new());
+
+pub fn last() -> String {
+    LAST.lock().unwrap().clone()
+}
+
+// Puts text on the host clipboard with OSC 52, which works over SSH and
+// needs no clipboard tool on either end
+pub fn copy(text: &str) {
+    forward(&format!("c;{}", STANDARD.encode(text)));
+}
+
+// Passes on an OSC 52 payload an app inside sent, e.g. "c;aGVsbG8="
+pub fn forward(payload: &str) {
+    if let Some((_, data)) = payload.split_once(';') {
+        *LAST.lock().unwrap() = data.to_string();
+    }
+
+    let sequence = format!("\x1b]52;{}\x07", payload);
+    let mut stdout = io::stdout();
+    if let Err(e) = stdout
+        .write_all(sequence.as_bytes())
+        .and_then(|_| stdout.flush())
+    {
+        eprintln!("Failed to copy to clipboard: {}", e);
+    }
+}
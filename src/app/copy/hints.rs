@@ -0,0 +1,239 @@
+use std::process::Stdio;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+};
+use regex::Regex;
+use vt100::Parser;
+
+use super::Pane;
+use super::history::History;
+use super::search::find;
+use super::selection::{Point, paint};
+
+pub enum HintOutcome {
+    Stay,
+    Exit,
+    Copy(String),
+    Open(String),
+}
+
+pub struct Hint {
+    pub label: String,
+    pub start: Point,
+    pub end: Point,
+    text: String,
+}
+
+// Labels every pattern match in view. Typing a label copies the match, typing
+// it in capitals hands it to the opener instead.
+pub struct HintMode {
+    pub pane: Pane,
+    pub hints: Vec<Hint>,
+    pub typed: String,
+}
+
+impl HintMode {
+    pub fn new(pane: Pane, parser: &mut Parser, patterns: &[Regex], alphabet: &str) -> Self {
+        let mut history = History::new(parser);
+        let lines = history.top()..history.bottom() + 1;
+
+        let mut found: Vec<(Point, Point)> = patterns
+            .iter()
+            .flat_map(|regex| find(&mut history, regex, lines.clone()))
+            .collect();
+        // Where patterns overlap the one starting first wins, then the longest
+        found.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+        let mut kept: Vec<(Point, Point)> = Vec::new();
+        for (start, end) in found {
+            if kept.last().is_none_or(|&(_, last)| start > last) {
+                kept.push((start, end));
+            }
+        }
+
+        // Closest to the bottom first, the same text shares one label
+        let mut texts: Vec<String> = Vec::new();
+        let matches: Vec<_> = kept
+            .into_iter()
+            .rev()
+            .map(|(start, end)| {
+                let text = history.text(start, end);
+                let index = match texts.iter().position(|seen| *seen == text) {
+                    Some(index) => index,
+                    None => {
+                        texts.push(text.clone());
+                        texts.len() - 1
+                    }
+                };
+                (start, end, text, index)
+            })
+            .collect();
+
+        let labels = labels(alphabet, texts.len());
+        let hints = matches
+            .into_iter()
+            .map(|(start, end, text, index)| Hint {
+                label: labels[index].clone(),
+                start,
+                end,
+                text,
+            })
+            .collect();
+
+        Self {
+            pane,
+            hints,
+            typed: String::new(),
+        }
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent) -> HintOutcome {
+        let c = match key.code {
+            KeyCode::Char(c) => c,
+            KeyCode::Backspace => {
+                self.typed.pop();
+                return HintOutcome::Stay;
+            }
+            _ => return HintOutcome::Exit,
+        };
+
+        self.typed.push(c.to_ascii_lowercase());
+        let typed = &self.typed;
+        if let Some(hint) = self.hints.iter().find(|hint| hint.label == *typed) {
+            let text = hint.text.clone();
+            return if c.is_ascii_uppercase() || key.modifiers.contains(KeyModifiers::SHIFT) {
+                HintOutcome::Open(text)
+            } else {
+                HintOutcome::Copy(text)
+            };
+        }
+
+        // A key that leads nowhere leaves hint mode
+        if self.hints.iter().any(|hint| hint.label.starts_with(typed)) {
+            HintOutcome::Stay
+        } else {
+            HintOutcome::Exit
+        }
+    }
+
+    // Underlines the matches still in the running and writes their labels
+    // over the start of each, the letters typed so far dimmed
+    pub fn draw(&self, buf: &mut Buffer, area: Rect, top: usize) {
+        let label_style = Style::default()
+            .bg(Color::Yellow)
+            .fg(Color::Black)
+            .add_modifier(Modifier::BOLD);
+        for hint in &self.hints {
+            if !hint.label.starts_with(&self.typed) {
+                continue;
+            }
+
+            paint(buf, area, top, (hint.start, hint.end), |cell| {
+                cell.modifier.insert(Modifier::UNDERLINED);
+            });
+            for (i, c) in hint.label.chars().enumerate() {
+                let point = Point {
+                    line: hint.start.line,
+                    col: hint.start.col.saturating_add(i as u16),
+                };
+                let style = if i < self.typed.len() {
+                    label_style.add_modifier(Modifier::DIM)
+                } else {
+                    label_style
+                };
+                paint(buf, area, top, (point, point), |cell| {
+                    cell.set_char(c).set_style(style);
+                });
+            }
+        }
+    }
+}
+
+// `count` labels of equal length, so none is a prefix of another
+fn labels(alphabet: &str, count: usize) -> Vec<String> {
+    let letters: Vec<char> = alphabet.chars().collect();
+    let mut len = 1;
+    while letters.len().pow(len) < count {
+        len += 1;
+    }
+
+    (0..count)
+        .map(|mut n| {
+            let mut label = vec![letters[0]; len as usize];
+            for slot in label.iter_mut().rev() {
+                *slot = letters[n % letters.len()];
+                n /= letters.len();
+            }
+            label.into_iter().collect()
+        })
+        .collect()
+}
+
+// The opener ready to run on a text, or nothing when it's left blank
+pub fn opener(opener: &str) -> Option<impl FnOnce(&str) + '_> {
+    (!opener.trim().is_empty()).then_some(move |text: &str| open(opener, text))
+}
+
+// Runs the opener with the match as its last argument, e.g. `xdg-open URL`
+pub fn open(opener: &str, text: &str) {
+    let mut words = opener.split_whitespace();
+    let Some(program) = words.next() else {
+        return;
+    };
+
+    let spawned = tokio::process::Command::new(program)
+        .args(words)
+        .arg(text)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    match spawned {
+        Ok(mut child) => {
+            tokio::spawn(async move { child.wait().await });
+        }
+        Err(e) => eprintln!("Failed to run {}: {}", program, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_usable(alphabet: &str, count: usize) {
+        let labels = labels(alphabet, count);
+        assert_eq!(labels.len(), count);
+        for (i, label) in labels.iter().enumerate() {
+            assert!(label.chars().all(|c| alphabet.contains(c)), "{}", label);
+            for (j, other) in labels.iter().enumerate() {
+                assert!(
+                    i == j || !other.starts_with(label.as_str()),
+                    "{} {}",
+                    label,
+                    other
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn labels_are_unique_and_prefix_free() {
+        for count in [0, 1, 8, 9, 10, 11, 80, 81, 82, 200] {
+            assert_usable("asdfghjkl", count);
+        }
+        for count in [1, 2, 3, 4, 5, 8, 9] {
+            assert_usable("ab", count);
+        }
+    }
+
+    #[test]
+    fn labels_grow_only_when_needed() {
+        assert_eq!(labels("abc", 3), ["a", "b", "c"]);
+        assert_eq!(labels("abc", 4), ["aa", "ab", "ac", "ba"].map(String::from));
+        assert_eq!(labels("abc", 9).last().map(String::as_str), Some("cc"));
+        assert_eq!(labels("abc", 10)[0], "aaa");
+    }
+}
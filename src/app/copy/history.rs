@@ -0,0 +1,132 @@
+use vt100::Parser;
+
+use crate::app::scroll::history_len;
+use crate::app::tap::images::marker_id;
+use crate::app::tap::links::untag;
+
+use super::selection::Point;
+
+// Reads any line of a parser's history or live screen by moving the view
+// around, numbered the same way as `scroll::View`. Whatever view it ends up
+// revealing is put in place when it's dropped.
+pub struct History<'a> {
+    parser: &'a mut Parser,
+    len: usize,
+    view: usize,
+}
+
+impl<'a> History<'a> {
+    pub fn new(parser: &'a mut Parser) -> Self {
+        let view = parser.screen().scrollback();
+        let len = history_len(parser);
+        Self { parser, len, view }
+    }
+
+    pub fn lines(&self) -> usize {
+        self.len + self.rows() as usize
+    }
+
+    pub fn rows(&self) -> u16 {
+        self.parser.screen().size().0
+    }
+
+    pub fn cols(&self) -> u16 {
+        self.parser.screen().size().1
+    }
+
+    // The line on the first row of the view
+    pub fn top(&self) -> usize {
+        self.len.saturating_sub(self.view)
+    }
+
+    // The line on the last row of the view
+    pub fn bottom(&self) -> usize {
+        self.top() + self.rows().saturating_sub(1) as usize
+    }
+
+    // vt100 puts the cursor one past the last column while a wrap is pending
+    pub fn cursor(&self) -> Point {
+        let (row, col) = self.parser.screen().cursor_position();
+        Point {
+            line: self.len + row as usize,
+            col: col.min(self.cols().saturating_sub(1)),
+        }
+    }
+
+    // Scrolls just far enough to bring `line` into view
+    pub fn reveal(&mut self, line: usize) {
+        let rows = self.rows() as usize;
+        let top = self.len - self.view;
+        if line < top {
+            self.view = self.len - line;
+        } else if line >= top + rows {
+            self.view = (self.len + rows).saturating_sub(line + 1);
+        }
+    }
+
+    // One string per cell, blanks and images read as a space and the second
+    // half of a wide character as an empty string
+    pub fn cells(&mut self, line: usize) -> Vec<String> {
+        let row = self.show(line);
+        let screen = self.parser.screen();
+        (0..screen.size().1)
+            .map(|col| match screen.cell(row, col) {
+                Some(cell) if cell.is_wide_continuation() => String::new(),
+                Some(cell) if cell.has_contents() && marker_id(&cell.contents()).is_none() => {
+                    untag(&cell.contents())
+                }
+                _ => String::from(" "),
+            })
+            .collect()
+    }
+
+    // Whether `line` runs on into the next one because it was too long
+    pub fn wrapped(&mut self, line: usize) -> bool {
+        let row = self.show(line);
+        self.parser.screen().row_wrapped(row)
+    }
+
+    pub fn text(&mut self, start: Point, end: Point) -> String {
+        let mut text = String::new();
+        for line in start.line..=end.line {
+            let cells = self.cells(line);
+            let from = if line == start.line { start.col } else { 0 } as usize;
+            let to = if line == end.line {
+                (end.col as usize).min(cells.len().saturating_sub(1))
+            } else {
+                cells.len().saturating_sub(1)
+            };
+
+            let part: String = cells.get(from..=to).unwrap_or_default().concat();
+            let last = line == end.line;
+            // Keep the blanks inside the selection, drop the ones that only
+            // pad a line out to the screen width
+            if (last && to + 1 < cells.len()) || (!last && self.wrapped(line)) {
+                text.push_str(&part);
+            } else {
+                text.push_str(part.trim_end());
+                if !last {
+                    text.push('\n');
+                }
+            }
+        }
+        text
+    }
+
+    // Puts `line` on screen and returns the row it's on
+    fn show(&mut self, line: usize) -> u16 {
+        if line < self.len {
+            self.parser.set_scrollback(self.len - line);
+            0
+        } else {
+            self.parser.set_scrollback(0);
+            (line - self.len) as u16
+        }
+    }
+}
+
+impl Drop for History<'_> {
+    fn drop(&mut self) {
+        self.parser.set_scrollback(self.view);
+    }
+}
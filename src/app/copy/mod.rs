@@ -0,0 +1,296 @@
+pub mod clipboard;
+pub mod hints;
+pub mod history;
+pub mod mouse;
+pub mod search;
+pub mod selection;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use vt100::Parser;
+
+use history::History;
+use selection::{Point, Selection, SelectionKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pane {
+    Owner,
+    Tenant,
+}
+
+pub enum CopyOutcome {
+    Stay,
+    Exit,
+    Yank(String),
+}
+
+// Keyboard selection with vi motions over one pane, history included
+pub struct CopyMode {
+    pub pane: Pane,
+    pub cursor: Point,
+    pub selection: Option<Selection>,
+    pending_g: bool,
+}
+
+impl CopyMode {
+    // Starts where the terminal cursor is
+    pub fn new(pane: Pane, parser: &mut Parser) -> Self {
+        let history = History::new(parser);
+        let mut copy = Self {
+            pane,
+            cursor: history.cursor(),
+            selection: None,
+            pending_g: false,
+        };
+        copy.fit(&history);
+        copy
+    }
+
+    // Pulls the cursor back onto the pane, e.g. after it shrank
+    pub fn fit(&mut self, history: &History) {
+        self.cursor.line = self.cursor.line.min(history.lines() - 1);
+        self.cursor.col = self.cursor.col.min(history.cols().saturating_sub(1));
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self.selection.as_ref().map(|s| s.kind) {
+            None => "copy",
+            Some(SelectionKind::Char) => "visual",
+            Some(SelectionKind::Line) => "visual line",
+        }
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent, parser: &mut Parser) -> CopyOutcome {
+        let mut history = History::new(parser);
+        self.fit(&history);
+        let last_line = history.lines() - 1;
+        let last_col = history.cols().saturating_sub(1);
+        let page = history.rows().max(1) as usize;
+        let pending_g = std::mem::take(&mut self.pending_g);
+        let cursor = &mut self.cursor;
+
+        match key.code {
+            KeyCode::Char('h') | KeyCode::Left => cursor.col = cursor.col.saturating_sub(1),
+            KeyCode::Char('l') | KeyCode::Right => cursor.col = (cursor.col + 1).min(last_col),
+            KeyCode::Char('k') | KeyCode::Up => cursor.line = cursor.line.saturating_sub(1),
+            KeyCode::Char('j') | KeyCode::Down => cursor.line = (cursor.line + 1).min(last_line),
+            KeyCode::Char('b') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                cursor.line = cursor.line.saturating_sub(page)
+            }
+            KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                cursor.line = (cursor.line + page).min(last_line)
+            }
+            KeyCode::PageUp => cursor.line = cursor.line.saturating_sub(page),
+            KeyCode::PageDown => cursor.line = (cursor.line + page).min(last_line),
+            KeyCode::Char('0') | KeyCode::Home => cursor.col = 0,
+            KeyCode::Char('$') | KeyCode::End => cursor.col = line_end(&mut history, cursor.line),
+            KeyCode::Char('w') => *cursor = next_word(&mut history, *cursor),
+            KeyCode::Char('b') => *cursor = prev_word(&mut history, *cursor),
+            KeyCode::Char('g') if pending_g => *cursor = Point { line: 0, col: 0 },
+            KeyCode::Char('g') => self.pending_g = true,
+            KeyCode::Char('G') => {
+                *cursor = Point {
+                    line: last_line,
+                    col: 0,
+                }
+            }
+            KeyCode::Char('v') => self.toggle_selection(SelectionKind::Char),
+            KeyCode::Char('V') => self.toggle_selection(SelectionKind::Line),
+            KeyCode::Char('y') | KeyCode::Enter => {
+                if let Some(selection) = &self.selection {
+                    return CopyOutcome::Yank(selection.text(&mut history));
+                }
+            }
+            KeyCode::Esc if self.selection.is_some() => self.selection = None,
+            KeyCode::Esc | KeyCode::Char('q') => return CopyOutcome::Exit,
+            _ => {}
+        }
+
+        if let Some(selection) = &mut self.selection {
+            selection.head = self.cursor;
+        }
+        history.reveal(self.cursor.line);
+        CopyOutcome::Stay
+    }
+
+    // `v` and `V` start a selection, switch between kinds or end it
+    fn toggle_selection(&mut self, kind: SelectionKind) {
+        match &mut self.selection {
+            Some(selection) if selection.kind == kind => self.selection = None,
+            Some(selection) => selection.kind = kind,
+            None => self.selection = Some(Selection::new(self.cursor, kind)),
+        }
+    }
+}
+
+// Blanks, word characters and punctuation, the three classes vi moves over
+fn class(cell: &str) -> u8 {
+    match cell.chars().next() {
+        Some(' ') => 0,
+        // The empty second half of a wide character
+        None => 1,
+        Some(c) if c.is_alphanumeric() || c == '_' => 1,
+        Some(_) => 2,
+    }
+}
+
+fn line_end(history: &mut History, line: usize) -> u16 {
+    let cells = history.cells(line);
+    cells.iter().rposition(|cell| cell != " ").unwrap_or(0) as u16
+}
+
+// `w`: past the rest of this word and the blanks after it
+fn next_word(history: &mut History, from: Point) -> Point {
+    let last_line = history.lines() - 1;
+    let mut point = from;
+    let mut cells = history.cells(point.line);
+    let start = class(&cells[point.col as usize]);
+    let mut blank = start == 0;
+
+    loop {
+        if (point.col as usize) + 1 < cells.len() {
+            point.col += 1;
+        } else if point.line < last_line {
+            point = Point {
+                line: point.line + 1,
+                col: 0,
+            };
+            cells = history.cells(point.line);
+            blank = true;
+        } else {
+            return point;
+        }
+
+        match class(&cells[point.col as usize]) {
+            0 => blank = true,
+            c if blank || c != start => return point,
+            _ => {}
+        }
+    }
+}
+
+// `b`: back over blanks to the start of the previous word
+fn prev_word(history: &mut History, from: Point) -> Point {
+    let mut point = from;
+    let mut cells = history.cells(point.line);
+
+    loop {
+        if point.col > 0 {
+            point.col -= 1;
+        } else if point.line > 0 {
+            point.line -= 1;
+            cells = history.cells(point.line);
+            point.col = cells.len().saturating_sub(1) as u16;
+        } else {
+            return point;
+        }
+
+        if class(&cells[point.col as usize]) != 0 {
+            break;
+        }
+    }
+
+    let word = class(&cells[point.col as usize]);
+    while point.col > 0 && class(&cells[point.col as usize - 1]) == word {
+        point.col -= 1;
+    }
+    point
+}
+
+// The run of cells of one class under `point`, what a double click selects
+pub fn word_at(history: &mut History, point: Point) -> (Point, Point) {
+    let cells = history.cells(point.line);
+    let col = (point.col as usize).min(cells.len().saturating_sub(1));
+    let word = class(&cells[col]);
+
+    let mut start = col;
+    while start > 0 && class(&cells[start - 1]) == word {
+        start -= 1;
+    }
+    let mut end = col;
+    while end + 1 < cells.len() && class(&cells[end + 1]) == word {
+        end += 1;
+    }
+
+    let at = |col: usize| Point {
+        line: point.line,
+        col: col as u16,
+    };
+    (at(start), at(end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser(rows: u16, cols: u16, output: &str) -> Parser {
+        let mut parser = Parser::new(rows, cols, 100);
+        parser.process(output.as_bytes());
+        parser
+    }
+
+    fn press(copy: &mut CopyMode, parser: &mut Parser, c: char) {
+        copy.handle_key(&KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE), parser);
+    }
+
+    fn point(line: usize, col: u16) -> Point {
+        Point { line, col }
+    }
+
+    #[test]
+    fn starts_on_a_full_last_column() {
+        // A full line leaves vt100's cursor one past the edge
+        let mut parser = parser(3, 10, "abcd efghi");
+        assert_eq!(parser.screen().cursor_position(), (0, 10));
+
+        let mut copy = CopyMode::new(Pane::Owner, &mut parser);
+        assert_eq!(copy.cursor, point(0, 9));
+
+        press(&mut copy, &mut parser, 'b');
+        assert_eq!(copy.cursor, point(0, 5));
+        // Nothing but blanks after it, so `w` runs to the end
+        press(&mut copy, &mut parser, 'w');
+        assert_eq!(copy.cursor, point(2, 9));
+    }
+
+    #[test]
+    fn follows_a_shrinking_pane() {
+        let mut parser = parser(5, 20, "one two three\r\n\r\n\r\n\r\nfour five");
+        let mut copy = CopyMode::new(Pane::Owner, &mut parser);
+        press(&mut copy, &mut parser, 'g');
+        press(&mut copy, &mut parser, 'g');
+        press(&mut copy, &mut parser, 'G');
+        for _ in 0..19 {
+            press(&mut copy, &mut parser, 'l');
+        }
+        assert_eq!(copy.cursor, point(4, 19));
+
+        parser.set_size(3, 8);
+        copy.fit(&History::new(&mut parser));
+        assert_eq!(copy.cursor, point(2, 7));
+
+        // Keys work from wherever the cursor ended up, resized or not
+        parser.set_size(2, 4);
+        press(&mut copy, &mut parser, 'w');
+        press(&mut copy, &mut parser, 'b');
+        press(&mut copy, &mut parser, 'b');
+        assert_eq!(copy.cursor, point(0, 0));
+    }
+
+    #[test]
+    fn moves_by_words() {
+        let mut parser = parser(3, 20, "foo.bar  baz\r\nqux");
+        let mut copy = CopyMode::new(Pane::Owner, &mut parser);
+        press(&mut copy, &mut parser, 'k');
+        press(&mut copy, &mut parser, '0');
+
+        let stops = [(0, 3), (0, 4), (0, 9), (1, 0)];
+        for (line, col) in stops {
+            press(&mut copy, &mut parser, 'w');
+            assert_eq!(copy.cursor, point(line, col));
+        }
+        for (line, col) in stops.iter().rev().skip(1).chain(&[(0, 0)]) {
+            press(&mut copy, &mut parser, 'b');
+            assert_eq!(copy.cursor, point(*line, *col));
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::Rect;
+use vt100::Parser;
+
+use super::history::History;
+use super::selection::{Point, Selection, SelectionKind};
+use super::{Pane, clipboard, word_at};
+
+// Clicks on the same cell closer together than this add up
+const MULTI_CLICK: Duration = Duration::from_millis(400);
+
+// Click and drag selection, a double click takes a word and a triple click
+// the whole line. The text goes to the clipboard when the button comes up.
+#[derive(Default)]
+pub struct MouseSelect {
+    selection: Option<(Pane, Selection)>,
+    selecting: bool,
+    last_click: Option<(Instant, Point)>,
+    clicks: u8,
+}
+
+impl MouseSelect {
+    pub fn selection(&self, pane: Pane) -> Option<&Selection> {
+        self.selection
+            .as_ref()
+            .filter(|(owner, _)| *owner == pane)
+            .map(|(_, selection)| selection)
+    }
+
+    pub fn selecting(&self) -> bool {
+        self.selecting
+    }
+
+    pub fn clear(&mut self) {
+        self.selection = None;
+        self.selecting = false;
+    }
+
+    // Takes the left button over a pane drawn at `area`, returns whether the
+    // event was used
+    pub fn handle(
+        &mut self,
+        pane: Pane,
+        parser: &RwLock<Parser>,
+        area: Rect,
+        m: &MouseEvent,
+    ) -> bool {
+        self.handle_at(pane, parser, area, m, Instant::now(), clipboard::copy)
+    }
+
+    // `copy` gets the text when a selection is let go
+    fn handle_at(
+        &mut self,
+        pane: Pane,
+        parser: &RwLock<Parser>,
+        area: Rect,
+        m: &MouseEvent,
+        now: Instant,
+        copy: impl FnOnce(&str),
+    ) -> bool {
+        if area.is_empty() {
+            return false;
+        }
+
+        let inside = m.column >= area.left()
+            && m.column < area.right()
+            && m.row >= area.top()
+            && m.row < area.bottom();
+
+        match m.kind {
+            MouseEventKind::Down(MouseButton::Left) if inside => {
+                self.start(pane, parser, area, m, now)
+            }
+            MouseEventKind::Drag(MouseButton::Left) if self.selecting => {
+                let point = point_at(parser, area, m);
+                if let Some((_, selection)) = &mut self.selection {
+                    selection.head = point;
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) if self.selecting => self.finish(parser, copy),
+            _ => return false,
+        }
+        true
+    }
+
+    fn start(
+        &mut self,
+        pane: Pane,
+        parser: &RwLock<Parser>,
+        area: Rect,
+        m: &MouseEvent,
+        now: Instant,
+    ) {
+        let point = point_at(parser, area, m);
+        self.clicks = match self.last_click {
+            Some((at, last)) if last == point && now - at < MULTI_CLICK => self.clicks % 3 + 1,
+            _ => 1,
+        };
+        self.last_click = Some((now, point));
+
+        let selection = match self.clicks {
+            2 => {
+                let mut parser = parser.write().unwrap();
+                let (start, end) = word_at(&mut History::new(&mut parser), point);
+                Selection {
+                    anchor: start,
+                    head: end,
+                    kind: SelectionKind::Char,
+                }
+            }
+            3 => Selection::new(point, SelectionKind::Line),
+            _ => Selection::new(point, SelectionKind::Char),
+        };
+        self.selection = Some((pane, selection));
+        self.selecting = true;
+    }
+
+    fn finish(&mut self, parser: &RwLock<Parser>, copy: impl FnOnce(&str)) {
+        self.selecting = false;
+        let Some((_, selection)) = &self.selection else {
+            return;
+        };
+
+        // A plain click selects nothing
+        if self.clicks == 1 && selection.anchor == selection.head {
+            self.selection = None;
+            return;
+        }
+
+        let mut parser = parser.write().unwrap();
+        let text = selection.text(&mut History::new(&mut parser));
+        if !text.is_empty() {
+            copy(&text);
+        }
+    }
+}
+
+// The history cell under the mouse, dragging past the pane sticks to its edge
+fn point_at(parser: &RwLock<Parser>, area: Rect, m: &MouseEvent) -> Point {
+    let col = m.column.clamp(area.left(), area.right().saturating_sub(1)) - area.left();
+    let row = m.row.clamp(area.top(), area.bottom().saturating_sub(1)) - area.top();
+    Point {
+        line: History::new(&mut parser.write().unwrap()).top() + row as usize,
+        col,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crossterm::event::KeyModifiers;
+
+    const AREA: Rect = Rect::new(2, 1, 20, 3);
+
+    fn parser() -> RwLock<Parser> {
+        let mut parser = Parser::new(3, 20, 100);
+        parser.process(b"foo.bar  baz\r\nqux quux");
+        RwLock::new(parser)
+    }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> MouseEvent {
+        MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+
+    fn point(line: usize, col: u16) -> Point {
+        Point { line, col }
+    }
+
+    // Presses at (ms, column, row) on the host
+    type Clicks = &'static [(u64, u16, u16)];
+
+    // Clicks away, letting go of the button after the last press, and
+    // returns what was copied
+    fn clicks(select: &mut MouseSelect, clicks: Clicks) -> Option<String> {
+        let parser = parser();
+        let start = Instant::now();
+        let mut copied = None;
+        for (i, &(ms, column, row)) in clicks.iter().enumerate() {
+            let at = start + Duration::from_millis(ms);
+            let mut handle = |kind| {
+                let m = mouse(kind, column, row);
+                let copy = |text: &str| copied = Some(text.to_string());
+                assert!(select.handle_at(Pane::Owner, &parser, AREA, &m, at, copy));
+            };
+            handle(MouseEventKind::Down(MouseButton::Left));
+            if i + 1 == clicks.len() {
+                handle(MouseEventKind::Up(MouseButton::Left));
+            }
+        }
+        copied
+    }
+
+    #[test]
+    fn counts_clicks() {
+        let cases: &[(Clicks, u8)] = &[
+            (&[(0, 7, 1)], 1),
+            (&[(0, 7, 1), (300, 7, 1)], 2),
+            (&[(0, 7, 1), (300, 7, 1), (600, 7, 1)], 3),
+            // A fourth click starts over
+            (&[(0, 7, 1), (100, 7, 1), (200, 7, 1), (300, 7, 1)], 1),
+            // Too slow, or on another cell
+            (&[(0, 7, 1), (400, 7, 1)], 1),
+            (&[(0, 7, 1), (100, 8, 1)], 1),
+        ];
+        for (presses, expected) in cases {
+            let mut select = MouseSelect::default();
+            clicks(&mut select, presses);
+            assert_eq!(select.clicks, *expected, "{:?}", presses);
+            assert!(!select.selecting());
+        }
+    }
+
+    #[test]
+    fn selects_by_clicks() {
+        // What ends up selected and the text that's copied
+        type Copied = Option<(Point, Point, &'static str)>;
+        let cases: &[(Clicks, Copied)] = &[
+            // A plain click selects nothing
+            (&[(0, 7, 1)], None),
+            // A word, the punctuation next to it, or the blanks between words
+            (
+                &[(0, 7, 1), (100, 7, 1)],
+                Some((point(0, 4), point(0, 6), "bar")),
+            ),
+            (
+                &[(0, 5, 1), (100, 5, 1)],
+                Some((point(0, 3), point(0, 3), ".")),
+            ),
+            (
+                &[(0, 10, 1), (100, 10, 1)],
+                Some((point(0, 7), point(0, 8), "  ")),
+            ),
+            // The whole line
+            (
+                &[(0, 3, 2), (100, 3, 2), (200, 3, 2)],
+                Some((point(1, 0), point(1, u16::MAX), "qux quux")),
+            ),
+        ];
+        for (presses, expected) in cases {
+            let mut select = MouseSelect::default();
+            let copied = clicks(&mut select, presses);
+            let found = select
+                .selection(Pane::Owner)
+                .map(|selection| selection.bounds())
+                .zip(copied);
+            let expected = expected.map(|(start, end, text)| ((start, end), text.to_string()));
+            assert_eq!(found, expected, "{:?}", presses);
+        }
+    }
+
+    #[test]
+    fn drags_across_lines() {
+        let parser = parser();
+        let mut select = MouseSelect::default();
+        let events = [
+            mouse(MouseEventKind::Down(MouseButton::Left), 6, 1),
+            mouse(MouseEventKind::Drag(MouseButton::Left), 0, 3),
+            // Past the pane it sticks to the edge
+            mouse(MouseEventKind::Drag(MouseButton::Left), 4, 9),
+            mouse(MouseEventKind::Up(MouseButton::Left), 4, 9),
+        ];
+        let mut copied = None;
+        for m in &events {
+            let copy = |text: &str| copied = Some(text.to_string());
+            assert!(select.handle_at(Pane::Owner, &parser, AREA, m, Instant::now(), copy));
+        }
+
+        let selection = select.selection(Pane::Owner).unwrap();
+        assert_eq!(selection.bounds(), (point(0, 4), point(2, 2)));
+        // Blanks inside the selection are kept
+        assert_eq!(copied.as_deref(), Some("bar  baz\nqux quux\n   "));
+        assert!(select.selection(Pane::Tenant).is_none());
+        // Only the button that started it is taken
+        let stray = mouse(MouseEventKind::Up(MouseButton::Left), 4, 2);
+        assert!(!select.handle(Pane::Owner, &parser, AREA, &stray));
+    }
+}
@@ -0,0 +1,292 @@
+use std::ops::Range;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use regex::{Regex, RegexBuilder};
+use vt100::Parser;
+
+use super::Pane;
+use super::history::History;
+use super::selection::Point;
+
+pub enum SearchOutcome {
+    Stay,
+    Exit,
+}
+
+// Incremental search over one pane's screen and history. The prompt takes
+// the keys until Enter, then `n` and `N` walk the matches.
+pub struct Search {
+    pub pane: Pane,
+    query: String,
+    regex: bool,
+    editing: bool,
+    invalid: bool,
+    matches: Vec<(Point, Point)>,
+    current: Option<usize>,
+    // Matches are looked for upwards from here, the view's last line when
+    // the search started
+    origin: usize,
+}
+
+impl Search {
+    pub fn new(pane: Pane, parser: &mut Parser) -> Self {
+        Self {
+            pane,
+            query: String::new(),
+            regex: false,
+            editing: true,
+            invalid: false,
+            matches: Vec::new(),
+            current: None,
+            origin: History::new(parser).bottom(),
+        }
+    }
+
+    pub fn matches(&self) -> &[(Point, Point)] {
+        &self.matches
+    }
+
+    pub fn current(&self) -> Option<(Point, Point)> {
+        self.current.map(|i| self.matches[i])
+    }
+
+    // e.g. "[search: foo_ 2/7]" or "[regex: fo( invalid]"
+    pub fn prompt(&self) -> String {
+        let kind = if self.regex { "regex" } else { "search" };
+        let cursor = if self.editing { "_" } else { "" };
+        let count = match self.current {
+            _ if self.invalid => String::from(" invalid"),
+            _ if self.query.is_empty() => String::new(),
+            Some(i) => format!(" {}/{}", self.matches.len() - i, self.matches.len()),
+            None => String::from(" 0/0"),
+        };
+        format!("[{}: {}{}{}]", kind, self.query, cursor, count)
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent, parser: &mut Parser) -> SearchOutcome {
+        let mut history = History::new(parser);
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        if self.editing {
+            match key.code {
+                KeyCode::Esc => return SearchOutcome::Exit,
+                KeyCode::Enter if self.query.is_empty() => return SearchOutcome::Exit,
+                KeyCode::Enter => self.editing = false,
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.update(&mut history);
+                }
+                // Ctrl+R switches between literal and regex
+                KeyCode::Char('r') if ctrl => {
+                    self.regex = !self.regex;
+                    self.update(&mut history);
+                }
+                KeyCode::Char('p') if ctrl => self.step(true),
+                KeyCode::Char('n') if ctrl => self.step(false),
+                KeyCode::Up => self.step(true),
+                KeyCode::Down => self.step(false),
+                KeyCode::Char(c) if !ctrl => {
+                    self.query.push(c);
+                    self.update(&mut history);
+                }
+                _ => {}
+            }
+        } else {
+            match key.code {
+                KeyCode::Char('n') => self.step(true),
+                KeyCode::Char('N') => self.step(false),
+                KeyCode::Char('/') => self.editing = true,
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Enter => return SearchOutcome::Exit,
+                _ => {}
+            }
+        }
+
+        if let Some((start, _)) = self.current() {
+            history.reveal(start.line);
+        }
+        SearchOutcome::Stay
+    }
+
+    fn update(&mut self, history: &mut History) {
+        self.matches.clear();
+        self.current = None;
+        self.invalid = false;
+        if self.query.is_empty() {
+            return;
+        }
+
+        let Ok(regex) = self.build() else {
+            self.invalid = true;
+            return;
+        };
+
+        self.matches = find(history, &regex, 0..history.lines());
+        // The closest match at or above where the search started
+        self.current = self
+            .matches
+            .iter()
+            .rposition(|(start, _)| start.line <= self.origin)
+            .or(self.matches.len().checked_sub(1));
+    }
+
+    // Lowercase queries ignore case, any capital makes them exact
+    fn build(&self) -> Result<Regex, regex::Error> {
+        let pattern = if self.regex {
+            self.query.clone()
+        } else {
+            regex::escape(&self.query)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.query.chars().any(char::is_uppercase))
+            .build()
+    }
+
+    // Up goes back in time, both ways wrap around
+    fn step(&mut self, up: bool) {
+        let len = self.matches.len();
+        if let Some(i) = self.current {
+            self.current = Some(if up {
+                (i + len - 1) % len
+            } else {
+                (i + 1) % len
+            });
+        }
+    }
+}
+
+// Every non-empty match of `regex` on each of `lines`. A line that wrapped
+// is searched together with the ones it runs on into, so a match may span
+// several lines.
+pub fn find(history: &mut History, regex: &Regex, lines: Range<usize>) -> Vec<(Point, Point)> {
+    let total = history.lines();
+    let mut matches = Vec::new();
+    let mut line = lines.start;
+    while line < lines.end {
+        let mut cells = Vec::new();
+        let mut points = Vec::new();
+        loop {
+            for (col, cell) in history.cells(line).into_iter().enumerate() {
+                cells.push(cell);
+                points.push(Point {
+                    line,
+                    col: col as u16,
+                });
+            }
+            line += 1;
+            if line >= total || !history.wrapped(line - 1) {
+                break;
+            }
+        }
+
+        // Byte offset where each cell starts in the text
+        let mut text = String::new();
+        let mut starts = Vec::with_capacity(cells.len());
+        for cell in &cells {
+            starts.push(text.len());
+            text.push_str(cell);
+        }
+        let cell_at = |byte: usize| starts.partition_point(|&start| start <= byte) - 1;
+
+        for m in regex.find_iter(&text).filter(|m| !m.is_empty()) {
+            let mut end = cell_at(m.end() - 1);
+            // Take in the second half of a wide character
+            while cells.get(end + 1).is_some_and(|cell| cell.is_empty()) {
+                end += 1;
+            }
+            matches.push((points[cell_at(m.start())], points[end]));
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser(cols: u16, output: &str) -> Parser {
+        let mut parser = Parser::new(4, cols, 100);
+        parser.process(output.as_bytes());
+        parser
+    }
+
+    fn point(line: usize, col: u16) -> Point {
+        Point { line, col }
+    }
+
+    fn find_all(parser: &mut Parser, pattern: &str) -> Vec<(Point, Point)> {
+        let mut history = History::new(parser);
+        let lines = 0..history.lines();
+        find(&mut history, &Regex::new(pattern).unwrap(), lines)
+    }
+
+    fn search(parser: &mut Parser, query: &str, regex: bool) -> Vec<(Point, Point)> {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let mut search = Search::new(Pane::Owner, parser);
+        if regex {
+            let toggle = KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL);
+            search.handle_key(&toggle, parser);
+        }
+        for c in query.chars() {
+            search.handle_key(&key(KeyCode::Char(c)), parser);
+        }
+        search.matches().to_vec()
+    }
+
+    #[test]
+    fn searches_literally_or_by_regex() {
+        let mut parser = parser(20, "a.c abc\r\nA.C");
+
+        let literal = search(&mut parser, "a.c", false);
+        assert_eq!(
+            literal,
+            [(point(0, 0), point(0, 2)), (point(1, 0), point(1, 2))]
+        );
+        // A capital makes the search exact
+        assert_eq!(
+            search(&mut parser, "A.C", false),
+            [(point(1, 0), point(1, 2))]
+        );
+
+        let regex = search(&mut parser, "a.c", true);
+        assert_eq!(regex.len(), 3);
+        assert_eq!(regex[1], (point(0, 4), point(0, 6)));
+
+        // A broken regex finds nothing, the same text as a literal does
+        assert!(search(&mut parser, "a(", true).is_empty());
+        assert!(search(&mut parser, "c\\", false).is_empty());
+    }
+
+    #[test]
+    fn maps_wide_characters_to_cells() {
+        let mut parser = parser(20, "中文 abc 文");
+
+        assert_eq!(find_all(&mut parser, "abc"), [(point(0, 5), point(0, 7))]);
+        assert_eq!(
+            find_all(&mut parser, "文"),
+            [(point(0, 2), point(0, 3)), (point(0, 9), point(0, 10))]
+        );
+        assert_eq!(find_all(&mut parser, "中文"), [(point(0, 0), point(0, 3))]);
+    }
+
+    #[test]
+    fn matches_across_wrapped_lines() {
+        let mut parser = parser(10, "0123456789abcdefghij0123\r\n89ab");
+
+        assert_eq!(
+            find_all(&mut parser, "89ab"),
+            [(point(0, 8), point(1, 1)), (point(3, 0), point(3, 3))]
+        );
+        assert_eq!(find_all(&mut parser, "j01"), [(point(1, 9), point(2, 1))]);
+        // A line break that isn't a wrap splits the text
+        assert!(find_all(&mut parser, "3\\s*8").is_empty());
+    }
+
+    #[test]
+    fn ignores_empty_patterns() {
+        let mut parser = parser(10, "abc");
+
+        assert!(find_all(&mut parser, "").is_empty());
+        assert!(find_all(&mut parser, "x*").is_empty());
+        assert!(search(&mut parser, "", false).is_empty());
+    }
+}
@@ -0,0 +1,101 @@
+use ratatui::{
+    buffer::{Buffer, Cell},
+    layout::Rect,
+    style::Modifier,
+};
+
+use super::history::History;
+
+// A cell in history line numbering, ordered top to bottom then left to right
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Point {
+    pub line: usize,
+    pub col: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionKind {
+    Char,
+    Line,
+}
+
+pub struct Selection {
+    pub anchor: Point,
+    pub head: Point,
+    pub kind: SelectionKind,
+}
+
+impl Selection {
+    pub fn new(at: Point, kind: SelectionKind) -> Self {
+        Self {
+            anchor: at,
+            head: at,
+            kind,
+        }
+    }
+
+    pub fn bounds(&self) -> (Point, Point) {
+        let (mut start, mut end) = if self.anchor <= self.head {
+            (self.anchor, self.head)
+        } else {
+            (self.head, self.anchor)
+        };
+
+        if self.kind == SelectionKind::Line {
+            start.col = 0;
+            end.col = u16::MAX;
+        }
+        (start, end)
+    }
+
+    pub fn text(&self, history: &mut History) -> String {
+        let (start, end) = self.bounds();
+        history.text(start, end)
+    }
+}
+
+// Flips the cells a selection covers in a pane drawn at `area`, whose first
+// row shows history line `top`
+pub fn highlight(buf: &mut Buffer, area: Rect, top: usize, selection: &Selection) {
+    paint(buf, area, top, selection.bounds(), |cell| {
+        cell.modifier.toggle(Modifier::REVERSED);
+    });
+}
+
+// Marks a single cell, e.g. the copy mode cursor
+pub fn mark(buf: &mut Buffer, area: Rect, top: usize, point: Point) {
+    paint(buf, area, top, (point, point), |cell| {
+        cell.modifier.toggle(Modifier::REVERSED);
+        cell.modifier.insert(Modifier::UNDERLINED);
+    });
+}
+
+// Runs `f` on every cell from `start` to `end` that's in view
+pub fn paint(
+    buf: &mut Buffer,
+    area: Rect,
+    top: usize,
+    (start, end): (Point, Point),
+    f: impl Fn(&mut Cell),
+) {
+    if area.is_empty() {
+        return;
+    }
+
+    let first = start.line.max(top);
+    let last = end.line.min(top + area.height as usize);
+    for line in first..=last {
+        let row = (line - top) as u16;
+        if row >= area.height {
+            break;
+        }
+
+        let from = if line == start.line { start.col } else { 0 };
+        let to = if line == end.line { end.col } else { u16::MAX };
+        for col in from..=to.min(area.width.saturating_sub(1)) {
+            if let Some(cell) = buf.cell_mut((area.x + col, area.y + row)) {
+                f(cell);
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{Error, bail};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Deserializer};
+
+// A key plus modifiers, written as "ctrl+shift+left", "home", "alt+x", "f5"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        // Fold the different ways crossterm can report the same key into one shape
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match code {
+            KeyCode::Char(c) if c.is_uppercase() => {
+                modifiers |= KeyModifiers::SHIFT;
+                KeyCode::Char(c.to_lowercase().next().unwrap_or(c))
+            }
+            KeyCode::Char(c) if !c.is_alphabetic() => {
+                // Shifted symbols already carry the shift in the character
+                if c != ' ' {
+                    modifiers.remove(KeyModifiers::SHIFT);
+                }
+                KeyCode::Char(c)
+            }
+            KeyCode::BackTab => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            code => code,
+        };
+
+        Self { code, modifiers }
+    }
+
+    pub fn from_event(key_event: &KeyEvent) -> Self {
+        Self::new(key_event.code, key_event.modifiers)
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        // "+" on its own (or as the last part of "ctrl++") is the plus key
+        let (mods, key) = match s.strip_suffix("++") {
+            Some(mods) => (mods, "+"),
+            None => match s.rsplit_once('+') {
+                Some((mods, key)) if !key.is_empty() => (mods, key),
+                _ => ("", s),
+            },
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for m in mods.split('+').filter(|m| !m.is_empty()) {
+            modifiers |= match m.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                other => bail!("unknown modifier `{}` in key `{}`", other, s),
+            };
+        }
+
+        let lower = key.to_lowercase();
+        let code = match lower.as_str() {
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" | "pgup" => KeyCode::PageUp,
+            "pagedown" | "pgdn" => KeyCode::PageDown,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "insert" | "ins" => KeyCode::Insert,
+            "delete" | "del" => KeyCode::Delete,
+            "backspace" | "bs" => KeyCode::Backspace,
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "esc" | "escape" => KeyCode::Esc,
+            "space" => KeyCode::Char(' '),
+            "plus" => KeyCode::Char('+'),
+            _ if key.chars().count() == 1 => KeyCode::Char(key.chars().next().unwrap_or(' ')),
+            f if f.starts_with('f') && f.len() > 1 => match f[1..].parse::<u8>() {
+                Ok(n) if (1..=24).contains(&n) => KeyCode::F(n),
+                _ => bail!("unknown key `{}`", key),
+            },
+            _ => bail!("unknown key `{}`", key),
+        };
+
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            code => write!(f, "{}", format!("{:?}", code).to_lowercase()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyChord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONE: KeyModifiers = KeyModifiers::NONE;
+    const SHIFT: KeyModifiers = KeyModifiers::SHIFT;
+    const CTRL: KeyModifiers = KeyModifiers::CONTROL;
+    const ALT: KeyModifiers = KeyModifiers::ALT;
+
+    fn chord(s: &str) -> KeyChord {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_chords() {
+        let cases = [
+            ("home", KeyCode::Home, NONE),
+            ("ctrl+shift+left", KeyCode::Left, CTRL | SHIFT),
+            ("Control+Meta+x", KeyCode::Char('x'), CTRL | ALT),
+            (" alt+pgdn ", KeyCode::PageDown, ALT),
+            ("f5", KeyCode::F(5), NONE),
+            ("shift+f24", KeyCode::F(24), SHIFT),
+            ("f", KeyCode::Char('f'), NONE),
+            ("space", KeyCode::Char(' '), NONE),
+            ("shift+space", KeyCode::Char(' '), SHIFT),
+            // The plus key on its own, after modifiers and by name
+            ("+", KeyCode::Char('+'), NONE),
+            ("ctrl++", KeyCode::Char('+'), CTRL),
+            ("ctrl+plus", KeyCode::Char('+'), CTRL),
+            ("esc", KeyCode::Esc, NONE),
+            ("shift+tab", KeyCode::Tab, SHIFT),
+        ];
+        for (s, code, modifiers) in cases {
+            assert_eq!(chord(s), KeyChord { code, modifiers }, "{}", s);
+        }
+    }
+
+    #[test]
+    fn normalizes_shift() {
+        // An uppercase letter and shift+letter are the same chord
+        assert_eq!(chord("A"), chord("shift+a"));
+        assert_eq!(
+            KeyChord::new(KeyCode::Char('A'), SHIFT),
+            KeyChord::new(KeyCode::Char('a'), SHIFT)
+        );
+        // Shifted symbols carry the shift in the character
+        assert_eq!(chord("shift+?"), chord("?"));
+        assert_eq!(
+            KeyChord::new(KeyCode::BackTab, SHIFT),
+            KeyChord::new(KeyCode::BackTab, NONE)
+        );
+        // Modifiers uncl doesn't track are dropped
+        assert_eq!(
+            KeyChord::new(KeyCode::Home, KeyModifiers::SUPER | CTRL),
+            KeyChord::new(KeyCode::Home, CTRL)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        for s in [
+            "hyper+x",
+            "ctrl+",
+            "f0",
+            "f25",
+            "fx",
+            "pageupp",
+            "ctrl+alt+nope",
+        ] {
+            assert!(s.parse::<KeyChord>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn displays_what_it_parses() {
+        for s in [
+            "home",
+            "ctrl+alt+shift+left",
+            "ctrl++",
+            "alt+space",
+            "f11",
+            "shift+pageup",
+            "ctrl+pagedown",
+            "backtab",
+            "esc",
+            "shift+x",
+        ] {
+            assert_eq!(chord(s).to_string(), s);
+            assert_eq!(chord(&chord(s).to_string()), chord(s));
+        }
+    }
+}
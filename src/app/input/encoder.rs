@@ -0,0 +1,753 @@
+use crossterm::event::{
+    KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers, MouseButton, MouseEvent,
+    MouseEventKind,
+};
+use vt100::{MouseProtocolEncoding, MouseProtocolMode, Screen};
+
+use crate::app::tap::kitty::{
+    DISAMBIGUATE, REPORT_ALL_KEYS, REPORT_ALTERNATES, REPORT_EVENTS, REPORT_TEXT,
+};
+
+// Turns crossterm key events into the bytes an xterm would send, following
+// the input modes the application switched on for its screen. Apps that
+// pushed kitty keyboard flags get the CSI-u encoding instead.
+pub struct KeyEncoder {
+    application_cursor: bool,
+    application_keypad: bool,
+    kitty_flags: u8,
+}
+
+impl KeyEncoder {
+    pub fn new(screen: &Screen, kitty_flags: u8) -> Self {
+        Self {
+            application_cursor: screen.application_cursor(),
+            application_keypad: screen.application_keypad(),
+            kitty_flags,
+        }
+    }
+
+    pub fn encode(&self, key: &KeyEvent) -> Option<Vec<u8>> {
+        if self.kitty_flags & (DISAMBIGUATE | REPORT_ALL_KEYS) != 0 {
+            return self.encode_kitty(key);
+        }
+
+        self.encode_legacy(key)
+    }
+
+    fn encode_legacy(&self, key: &KeyEvent) -> Option<Vec<u8>> {
+        if key.kind == KeyEventKind::Release {
+            return None;
+        }
+
+        let modifiers = key.modifiers;
+        let modifier = modifier_param(modifiers);
+
+        if self.application_keypad
+            && key.state.contains(KeyEventState::KEYPAD)
+            && let Some(bytes) = keypad(key.code)
+        {
+            return Some(bytes);
+        }
+
+        let bytes = match key.code {
+            KeyCode::Char(c) => encode_char(c, modifiers),
+            KeyCode::Enter => with_alt(modifiers, b"\r"),
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => b"\x1b[Z".to_vec(),
+            KeyCode::Tab => with_alt(modifiers, b"\t"),
+            KeyCode::BackTab => b"\x1b[Z".to_vec(),
+            KeyCode::Backspace if modifiers.contains(KeyModifiers::CONTROL) => {
+                with_alt(modifiers, b"\x08")
+            }
+            KeyCode::Backspace => with_alt(modifiers, b"\x7f"),
+            KeyCode::Esc => with_alt(modifiers, b"\x1b"),
+            KeyCode::Null => vec![0],
+            KeyCode::Up => self.cursor(b'A', modifier),
+            KeyCode::Down => self.cursor(b'B', modifier),
+            KeyCode::Right => self.cursor(b'C', modifier),
+            KeyCode::Left => self.cursor(b'D', modifier),
+            KeyCode::Home => self.cursor(b'H', modifier),
+            KeyCode::End => self.cursor(b'F', modifier),
+            KeyCode::Insert => tilde(2, modifier),
+            KeyCode::Delete => tilde(3, modifier),
+            KeyCode::PageUp => tilde(5, modifier),
+            KeyCode::PageDown => tilde(6, modifier),
+            KeyCode::F(n @ 1..=4) => {
+                let letter = b'P' + (n - 1);
+                if modifier > 1 {
+                    format!("\x1b[1;{}{}", modifier, letter as char).into_bytes()
+                } else {
+                    vec![0x1b, b'O', letter]
+                }
+            }
+            KeyCode::F(n @ 5..=12) => {
+                // The numbering skips 16 and 22 for historical reasons
+                let code = [15, 17, 18, 19, 20, 21, 23, 24][(n - 5) as usize];
+                tilde(code, modifier)
+            }
+            _ => return None,
+        };
+
+        Some(bytes)
+    }
+
+    fn encode_kitty(&self, key: &KeyEvent) -> Option<Vec<u8>> {
+        let flags = self.kitty_flags;
+        let all_keys = flags & REPORT_ALL_KEYS != 0;
+        let keypad = key.state.contains(KeyEventState::KEYPAD);
+
+        let event = match key.kind {
+            _ if flags & REPORT_EVENTS == 0 => 1,
+            KeyEventKind::Press => 1,
+            KeyEventKind::Repeat => 2,
+            KeyEventKind::Release => 3,
+        };
+        if key.kind == KeyEventKind::Release && event != 3 {
+            return None;
+        }
+
+        let mut modifier = kitty_modifiers(key.modifiers);
+        if key.code == KeyCode::BackTab {
+            modifier |= 1;
+        }
+        let modifier = modifier + 1;
+        let text_key = !key
+            .modifiers
+            .intersects(KeyModifiers::all() - KeyModifiers::SHIFT);
+
+        // Typed text and Enter/Tab/Backspace stay legacy unless the app asked
+        // for every key, so a shell still works after an app dies mid-mode
+        if !all_keys {
+            let legacy = match key.code {
+                KeyCode::Char(_) => text_key && !keypad,
+                KeyCode::Enter | KeyCode::Tab | KeyCode::Backspace => modifier == 1,
+                _ => false,
+            };
+            if legacy {
+                return if event == 3 {
+                    None
+                } else {
+                    self.encode_legacy(key)
+                };
+            }
+        }
+
+        let (number, suffix) = match key.code {
+            KeyCode::Up => (1, b'A'),
+            KeyCode::Down => (1, b'B'),
+            KeyCode::Right => (1, b'C'),
+            KeyCode::Left => (1, b'D'),
+            KeyCode::Home => (1, b'H'),
+            KeyCode::End => (1, b'F'),
+            KeyCode::F(1) => (1, b'P'),
+            KeyCode::F(2) => (1, b'Q'),
+            // `CSI R` would read as a cursor position report
+            KeyCode::F(3) => (13, b'~'),
+            KeyCode::F(4) => (1, b'S'),
+            KeyCode::F(n @ 5..=12) => ([15, 17, 18, 19, 20, 21, 23, 24][(n - 5) as usize], b'~'),
+            KeyCode::F(n @ 13..=35) => (57376 + (n as u32 - 13), b'u'),
+            KeyCode::Insert => (2, b'~'),
+            KeyCode::Delete => (3, b'~'),
+            KeyCode::PageUp => (5, b'~'),
+            KeyCode::PageDown => (6, b'~'),
+            KeyCode::Esc => (27, b'u'),
+            KeyCode::Enter if keypad => (57414, b'u'),
+            KeyCode::Enter => (13, b'u'),
+            KeyCode::Tab | KeyCode::BackTab => (9, b'u'),
+            KeyCode::Backspace => (127, b'u'),
+            KeyCode::CapsLock => (57358, b'u'),
+            KeyCode::ScrollLock => (57359, b'u'),
+            KeyCode::NumLock => (57360, b'u'),
+            KeyCode::PrintScreen => (57361, b'u'),
+            KeyCode::Pause => (57362, b'u'),
+            KeyCode::Menu => (57363, b'u'),
+            KeyCode::Char(c) if keypad => match kitty_keypad(c) {
+                Some(number) => (number, b'u'),
+                None => (c as u32, b'u'),
+            },
+            KeyCode::Char(c) => (c.to_lowercase().next().unwrap_or(c) as u32, b'u'),
+            _ => return None,
+        };
+
+        // Unmodified presses of the legacy function keys keep their old form
+        if suffix != b'u' && modifier == 1 && event == 1 && !all_keys {
+            return self.encode_legacy(key);
+        }
+
+        let mut out = String::from("\x1b[");
+        if suffix == b'u' || suffix == b'~' || modifier > 1 || event > 1 {
+            out.push_str(&number.to_string());
+        }
+
+        if flags & REPORT_ALTERNATES != 0
+            && let KeyCode::Char(c) = key.code
+            && c.is_uppercase()
+        {
+            out.push_str(&format!(":{}", c as u32));
+        }
+
+        let text = match key.code {
+            KeyCode::Char(c) if flags & REPORT_TEXT != 0 && all_keys && text_key && event != 3 => {
+                Some(c as u32)
+            }
+            _ => None,
+        };
+
+        if modifier > 1 || event > 1 || text.is_some() {
+            out.push(';');
+            if modifier > 1 || event > 1 {
+                out.push_str(&modifier.to_string());
+            }
+            if event > 1 {
+                out.push_str(&format!(":{}", event));
+            }
+        }
+        if let Some(text) = text {
+            out.push_str(&format!(";{}", text));
+        }
+
+        out.push(suffix as char);
+        Some(out.into_bytes())
+    }
+
+    fn cursor(&self, letter: u8, modifier: u8) -> Vec<u8> {
+        if modifier > 1 {
+            format!("\x1b[1;{}{}", modifier, letter as char).into_bytes()
+        } else if self.application_cursor {
+            vec![0x1b, b'O', letter]
+        } else {
+            vec![0x1b, b'[', letter]
+        }
+    }
+}
+
+// xterm encodes modifiers as 1 + shift(1) + alt(2) + ctrl(4)
+fn modifier_param(modifiers: KeyModifiers) -> u8 {
+    let mut param = 1;
+    if modifiers.contains(KeyModifiers::SHIFT) {
+        param += 1;
+    }
+    if modifiers.contains(KeyModifiers::ALT) {
+        param += 2;
+    }
+    if modifiers.contains(KeyModifiers::CONTROL) {
+        param += 4;
+    }
+    param
+}
+
+// kitty adds super, hyper and meta on top of the xterm bits
+fn kitty_modifiers(modifiers: KeyModifiers) -> u32 {
+    let mut bits = (modifier_param(modifiers) - 1) as u32;
+    if modifiers.contains(KeyModifiers::SUPER) {
+        bits |= 8;
+    }
+    if modifiers.contains(KeyModifiers::HYPER) {
+        bits |= 16;
+    }
+    if modifiers.contains(KeyModifiers::META) {
+        bits |= 32;
+    }
+    bits
+}
+
+fn kitty_keypad(c: char) -> Option<u32> {
+    Some(match c {
+        '0'..='9' => 57399 + (c as u32 - '0' as u32),
+        '.' => 57409,
+        '/' => 57410,
+        '*' => 57411,
+        '-' => 57412,
+        '+' => 57413,
+        '=' => 57415,
+        _ => return None,
+    })
+}
+
+fn tilde(code: u8, modifier: u8) -> Vec<u8> {
+    if modifier > 1 {
+        format!("\x1b[{};{}~", code, modifier).into_bytes()
+    } else {
+        format!("\x1b[{}~", code).into_bytes()
+    }
+}
+
+fn with_alt(modifiers: KeyModifiers, bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len() + 1);
+    if modifiers.contains(KeyModifiers::ALT) {
+        out.push(0x1b);
+    }
+    out.extend_from_slice(bytes);
+    out
+}
+
+fn encode_char(c: char, modifiers: KeyModifiers) -> Vec<u8> {
+    if modifiers.contains(KeyModifiers::CONTROL)
+        && let Some(byte) = control_byte(c)
+    {
+        return with_alt(modifiers, &[byte]);
+    }
+
+    let mut buf = [0u8; 4];
+    with_alt(modifiers, c.encode_utf8(&mut buf).as_bytes())
+}
+
+fn control_byte(c: char) -> Option<u8> {
+    match c {
+        'a'..='z' => Some(c as u8 - b'a' + 1),
+        'A'..='Z' => Some(c as u8 - b'A' + 1),
+        '@' | ' ' | '2' => Some(0x00),
+        '[' | '3' => Some(0x1b),
+        '\\' | '4' => Some(0x1c),
+        ']' | '5' => Some(0x1d),
+        '^' | '~' | '6' => Some(0x1e),
+        '_' | '/' | '-' | '7' => Some(0x1f),
+        '?' | '8' => Some(0x7f),
+        _ => None,
+    }
+}
+
+// Application keypad mode (DECKPAM) sends SS3 sequences for the keypad
+fn keypad(code: KeyCode) -> Option<Vec<u8>> {
+    let letter = match code {
+        KeyCode::Char(c @ '0'..='9') => b'p' + (c as u8 - b'0'),
+        KeyCode::Char('.') => b'n',
+        KeyCode::Char('+') => b'k',
+        KeyCode::Char('-') => b'm',
+        KeyCode::Char('*') => b'j',
+        KeyCode::Char('/') => b'o',
+        KeyCode::Char('=') => b'X',
+        KeyCode::Enter => b'M',
+        _ => return None,
+    };
+    Some(vec![0x1b, b'O', letter])
+}
+
+// Turns crossterm mouse events at screen-local cells into the reports the
+// application asked for with its mouse tracking and encoding modes
+pub struct MouseEncoder {
+    mode: MouseProtocolMode,
+    encoding: MouseProtocolEncoding,
+}
+
+impl MouseEncoder {
+    pub fn new(screen: &Screen) -> Self {
+        Self {
+            mode: screen.mouse_protocol_mode(),
+            encoding: screen.mouse_protocol_encoding(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.mode != MouseProtocolMode::None
+    }
+
+    pub fn encode(&self, event: &MouseEvent, col: u16, row: u16) -> Option<Vec<u8>> {
+        use MouseProtocolMode::{AnyMotion, ButtonMotion, Press};
+
+        if !self.enabled() {
+            return None;
+        }
+
+        let (button, release) = match event.kind {
+            MouseEventKind::Down(button) => (mouse_button(button), false),
+            MouseEventKind::Up(button) if self.mode != Press => (mouse_button(button), true),
+            MouseEventKind::Drag(button) if matches!(self.mode, ButtonMotion | AnyMotion) => {
+                (mouse_button(button) + 32, false)
+            }
+            // Motion with no button held reports as button 3
+            MouseEventKind::Moved if self.mode == AnyMotion => (3 + 32, false),
+            MouseEventKind::ScrollUp => (64, false),
+            MouseEventKind::ScrollDown => (65, false),
+            MouseEventKind::ScrollLeft => (66, false),
+            MouseEventKind::ScrollRight => (67, false),
+            _ => return None,
+        };
+
+        // X10 mode never reports modifiers
+        let mut code = button;
+        if self.mode != Press {
+            code += mouse_modifiers(event.modifiers);
+        }
+
+        let (x, y) = (col as u32 + 1, row as u32 + 1);
+        if self.encoding == MouseProtocolEncoding::Sgr {
+            let suffix = if release { 'm' } else { 'M' };
+            return Some(format!("\x1b[<{};{};{}{}", code, x, y, suffix).into_bytes());
+        }
+
+        // The older encodings can't tell which button was released
+        if release {
+            code = 3 + (code & !3);
+        }
+
+        let mut out = b"\x1b[M".to_vec();
+        for value in [code, x, y] {
+            let value = value + 32;
+            match self.encoding {
+                MouseProtocolEncoding::Utf8 => {
+                    let c = char::from_u32(value).filter(|_| value < 0x800)?;
+                    let mut buf = [0u8; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                // Cells past 223 can't be reported in a single byte
+                _ => out.push(u8::try_from(value).ok()?),
+            }
+        }
+        Some(out)
+    }
+}
+
+fn mouse_button(button: MouseButton) -> u32 {
+    match button {
+        MouseButton::Left => 0,
+        MouseButton::Middle => 1,
+        MouseButton::Right => 2,
+    }
+}
+
+// Mouse reports pack shift(4), alt(8) and ctrl(16) into the button code
+fn mouse_modifiers(modifiers: KeyModifiers) -> u32 {
+    let mut bits = 0;
+    if modifiers.contains(KeyModifiers::SHIFT) {
+        bits |= 4;
+    }
+    if modifiers.contains(KeyModifiers::ALT) {
+        bits |= 8;
+    }
+    if modifiers.contains(KeyModifiers::CONTROL) {
+        bits |= 16;
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoder(modes: &[u8]) -> KeyEncoder {
+        let mut parser = vt100::Parser::new(24, 80, 0);
+        parser.process(modes);
+        KeyEncoder::new(parser.screen(), 0)
+    }
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    const NONE: KeyModifiers = KeyModifiers::NONE;
+    const SHIFT: KeyModifiers = KeyModifiers::SHIFT;
+    const CTRL: KeyModifiers = KeyModifiers::CONTROL;
+    const ALT: KeyModifiers = KeyModifiers::ALT;
+
+    const APP_CURSOR: &[u8] = b"\x1b[?1h";
+    const APP_KEYPAD: &[u8] = b"\x1b=";
+
+    #[test]
+    fn encodes_keys() {
+        let cases: &[(&[u8], KeyEvent, &[u8])] = &[
+            // Plain characters, including non-ASCII
+            (b"", key(KeyCode::Char('a'), NONE), b"a"),
+            (b"", key(KeyCode::Char('A'), SHIFT), b"A"),
+            (b"", key(KeyCode::Char('é'), NONE), "é".as_bytes()),
+            // Control characters
+            (b"", key(KeyCode::Char('c'), CTRL), b"\x03"),
+            (b"", key(KeyCode::Char(' '), CTRL), b"\x00"),
+            (b"", key(KeyCode::Char('@'), CTRL), b"\x00"),
+            (b"", key(KeyCode::Char('/'), CTRL), b"\x1f"),
+            (b"", key(KeyCode::Char('['), CTRL), b"\x1b"),
+            (b"", key(KeyCode::Char(']'), CTRL), b"\x1d"),
+            (b"", key(KeyCode::Char('?'), CTRL), b"\x7f"),
+            // Alt prefixes ESC and keeps multi-byte characters intact
+            (b"", key(KeyCode::Char('x'), ALT), b"\x1bx"),
+            (b"", key(KeyCode::Char('ö'), ALT), "\x1bö".as_bytes()),
+            (b"", key(KeyCode::Char('c'), CTRL | ALT), b"\x1b\x03"),
+            // Editing keys
+            (b"", key(KeyCode::Enter, NONE), b"\r"),
+            (b"", key(KeyCode::Enter, ALT), b"\x1b\r"),
+            (b"", key(KeyCode::Tab, NONE), b"\t"),
+            (b"", key(KeyCode::BackTab, SHIFT), b"\x1b[Z"),
+            (b"", key(KeyCode::Backspace, NONE), b"\x7f"),
+            (b"", key(KeyCode::Backspace, CTRL), b"\x08"),
+            (b"", key(KeyCode::Backspace, ALT), b"\x1b\x7f"),
+            (b"", key(KeyCode::Esc, NONE), b"\x1b"),
+            // Cursor keys follow DECCKM
+            (b"", key(KeyCode::Up, NONE), b"\x1b[A"),
+            (b"", key(KeyCode::Left, NONE), b"\x1b[D"),
+            (APP_CURSOR, key(KeyCode::Up, NONE), b"\x1bOA"),
+            (APP_CURSOR, key(KeyCode::Right, NONE), b"\x1bOC"),
+            (b"", key(KeyCode::Home, NONE), b"\x1b[H"),
+            (APP_CURSOR, key(KeyCode::Home, NONE), b"\x1bOH"),
+            (b"", key(KeyCode::End, NONE), b"\x1b[F"),
+            (APP_CURSOR, key(KeyCode::End, NONE), b"\x1bOF"),
+            // Modified cursor keys ignore DECCKM
+            (b"", key(KeyCode::Left, CTRL), b"\x1b[1;5D"),
+            (b"", key(KeyCode::Right, SHIFT), b"\x1b[1;2C"),
+            (APP_CURSOR, key(KeyCode::Up, ALT), b"\x1b[1;3A"),
+            (b"", key(KeyCode::Home, SHIFT), b"\x1b[1;2H"),
+            (b"", key(KeyCode::End, CTRL | SHIFT), b"\x1b[1;6F"),
+            // Tilde keys
+            (b"", key(KeyCode::Insert, NONE), b"\x1b[2~"),
+            (b"", key(KeyCode::Delete, NONE), b"\x1b[3~"),
+            (b"", key(KeyCode::Delete, CTRL), b"\x1b[3;5~"),
+            (b"", key(KeyCode::PageUp, NONE), b"\x1b[5~"),
+            (b"", key(KeyCode::PageDown, SHIFT), b"\x1b[6;2~"),
+            // Function keys
+            (b"", key(KeyCode::F(1), NONE), b"\x1bOP"),
+            (b"", key(KeyCode::F(4), NONE), b"\x1bOS"),
+            (b"", key(KeyCode::F(1), CTRL), b"\x1b[1;5P"),
+            (b"", key(KeyCode::F(5), NONE), b"\x1b[15~"),
+            (b"", key(KeyCode::F(6), NONE), b"\x1b[17~"),
+            (b"", key(KeyCode::F(12), NONE), b"\x1b[24~"),
+            (b"", key(KeyCode::F(12), SHIFT | ALT), b"\x1b[24;4~"),
+        ];
+
+        for (modes, event, expected) in cases {
+            assert_eq!(
+                encoder(modes).encode(event).as_deref(),
+                Some(*expected),
+                "modes {:?}, key {:?}",
+                modes,
+                event
+            );
+        }
+    }
+
+    #[test]
+    fn encodes_keypad() {
+        let keypad = |code| {
+            let mut event = key(code, NONE);
+            event.state = KeyEventState::KEYPAD;
+            event
+        };
+
+        let cases: &[(&[u8], KeyEvent, &[u8])] = &[
+            (b"", keypad(KeyCode::Char('5')), b"5"),
+            (APP_KEYPAD, keypad(KeyCode::Char('5')), b"\x1bOu"),
+            (APP_KEYPAD, keypad(KeyCode::Char('0')), b"\x1bOp"),
+            (APP_KEYPAD, keypad(KeyCode::Enter), b"\x1bOM"),
+            (APP_KEYPAD, keypad(KeyCode::Char('+')), b"\x1bOk"),
+            // Only real keypad keys switch
+            (APP_KEYPAD, key(KeyCode::Char('5'), NONE), b"5"),
+        ];
+
+        for (modes, event, expected) in cases {
+            assert_eq!(
+                encoder(modes).encode(event).as_deref(),
+                Some(*expected),
+                "modes {:?}, key {:?}",
+                modes,
+                event
+            );
+        }
+    }
+
+    #[test]
+    fn encodes_kitty_keys() {
+        let kitty = |flags, event: KeyEvent| {
+            KeyEncoder::new(vt100::Parser::new(24, 80, 0).screen(), flags).encode(&event)
+        };
+        let with_kind = |mut event: KeyEvent, kind| {
+            event.kind = kind;
+            event
+        };
+
+        let base = DISAMBIGUATE;
+        let events = DISAMBIGUATE | REPORT_EVENTS;
+        let all = DISAMBIGUATE | REPORT_ALL_KEYS;
+
+        let cases: &[(u8, KeyEvent, Option<&[u8]>)] = &[
+            // Text and the shell keys stay legacy
+            (base, key(KeyCode::Char('a'), NONE), Some(b"a")),
+            (base, key(KeyCode::Char('A'), SHIFT), Some(b"A")),
+            (base, key(KeyCode::Enter, NONE), Some(b"\r")),
+            (base, key(KeyCode::Tab, NONE), Some(b"\t")),
+            (base, key(KeyCode::Backspace, NONE), Some(b"\x7f")),
+            // Ambiguous keys get CSI-u
+            (base, key(KeyCode::Esc, NONE), Some(b"\x1b[27u")),
+            (base, key(KeyCode::Char('i'), CTRL), Some(b"\x1b[105;5u")),
+            (base, key(KeyCode::Char('a'), ALT), Some(b"\x1b[97;3u")),
+            (
+                base,
+                key(KeyCode::Char('A'), CTRL | SHIFT),
+                Some(b"\x1b[97;6u"),
+            ),
+            (base, key(KeyCode::Enter, SHIFT), Some(b"\x1b[13;2u")),
+            (base, key(KeyCode::BackTab, SHIFT), Some(b"\x1b[9;2u")),
+            // Functional keys
+            (base, key(KeyCode::Up, NONE), Some(b"\x1b[A")),
+            (base, key(KeyCode::Left, CTRL), Some(b"\x1b[1;5D")),
+            (base, key(KeyCode::F(3), NONE), Some(b"\x1bOR")),
+            (base, key(KeyCode::F(3), SHIFT), Some(b"\x1b[13;2~")),
+            (base, key(KeyCode::Delete, ALT), Some(b"\x1b[3;3~")),
+            (base, key(KeyCode::F(13), NONE), Some(b"\x1b[57376u")),
+            // Event types
+            (
+                base,
+                with_kind(key(KeyCode::Esc, NONE), KeyEventKind::Release),
+                None,
+            ),
+            (
+                events,
+                with_kind(key(KeyCode::Esc, NONE), KeyEventKind::Release),
+                Some(b"\x1b[27;1:3u"),
+            ),
+            (
+                events,
+                with_kind(key(KeyCode::Up, NONE), KeyEventKind::Repeat),
+                Some(b"\x1b[1;1:2A"),
+            ),
+            (
+                events,
+                with_kind(key(KeyCode::Char('a'), NONE), KeyEventKind::Release),
+                None,
+            ),
+            // Everything as escape codes, with alternates and text
+            (all, key(KeyCode::Char('a'), NONE), Some(b"\x1b[97u")),
+            (all, key(KeyCode::Enter, NONE), Some(b"\x1b[13u")),
+            (
+                all | REPORT_ALTERNATES,
+                key(KeyCode::Char('A'), SHIFT),
+                Some(b"\x1b[97:65;2u"),
+            ),
+            (
+                all | REPORT_TEXT,
+                key(KeyCode::Char('a'), NONE),
+                Some(b"\x1b[97;;97u"),
+            ),
+        ];
+
+        for (flags, event, expected) in cases {
+            assert_eq!(
+                kitty(*flags, *event).as_deref(),
+                *expected,
+                "flags {:#b}, key {:?}",
+                flags,
+                event
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_releases_and_unknown_keys() {
+        let mut release = key(KeyCode::Char('a'), NONE);
+        release.kind = KeyEventKind::Release;
+
+        assert_eq!(encoder(b"").encode(&release), None);
+        assert_eq!(encoder(b"").encode(&key(KeyCode::CapsLock, NONE)), None);
+        assert_eq!(encoder(b"").encode(&key(KeyCode::F(20), NONE)), None);
+    }
+
+    fn mouse(kind: MouseEventKind, modifiers: KeyModifiers) -> MouseEvent {
+        MouseEvent {
+            kind,
+            column: 0,
+            row: 0,
+            modifiers,
+        }
+    }
+
+    // Modes, event, screen-local cell and the expected report
+    type MouseCase<'a> = (&'a [u8], MouseEvent, (u16, u16), Option<&'a [u8]>);
+
+    #[test]
+    fn encodes_mouse() {
+        use MouseEventKind::{Down, Drag, Moved, ScrollDown, ScrollUp, Up};
+        let left = MouseButton::Left;
+
+        let cases: &[MouseCase] = &[
+            // Nothing is reported until the app asks
+            (b"", mouse(Down(left), NONE), (0, 0), None),
+            // X10 reports presses only, without modifiers
+            (
+                b"\x1b[?9h",
+                mouse(Down(left), CTRL),
+                (0, 0),
+                Some(b"\x1b[M !!"),
+            ),
+            (b"\x1b[?9h", mouse(Up(left), NONE), (0, 0), None),
+            // Normal tracking, default encoding
+            (
+                b"\x1b[?1000h",
+                mouse(Down(left), NONE),
+                (4, 9),
+                Some(b"\x1b[M %*"),
+            ),
+            (
+                b"\x1b[?1000h",
+                mouse(Up(left), NONE),
+                (4, 9),
+                Some(b"\x1b[M#%*"),
+            ),
+            (
+                b"\x1b[?1000h",
+                mouse(Down(MouseButton::Right), SHIFT),
+                (0, 0),
+                Some(b"\x1b[M&!!"),
+            ),
+            (
+                b"\x1b[?1000h",
+                mouse(ScrollUp, NONE),
+                (0, 0),
+                Some(b"\x1b[M`!!"),
+            ),
+            (b"\x1b[?1000h", mouse(Drag(left), NONE), (0, 0), None),
+            (b"\x1b[?1000h", mouse(Down(left), NONE), (300, 0), None),
+            // Button-event and any-event tracking
+            (
+                b"\x1b[?1002h",
+                mouse(Drag(left), NONE),
+                (1, 1),
+                Some(b"\x1b[M@\"\""),
+            ),
+            (b"\x1b[?1002h", mouse(Moved, NONE), (1, 1), None),
+            (
+                b"\x1b[?1003h",
+                mouse(Moved, NONE),
+                (1, 1),
+                Some(b"\x1b[MC\"\""),
+            ),
+            // UTF-8 encoding stretches past column 223
+            (
+                b"\x1b[?1000h\x1b[?1005h",
+                mouse(Down(left), NONE),
+                (300, 0),
+                Some("\x1b[M \u{14d}!".as_bytes()),
+            ),
+            // SGR keeps the released button and modifiers
+            (
+                b"\x1b[?1000h\x1b[?1006h",
+                mouse(Down(left), ALT | CTRL),
+                (9, 19),
+                Some(b"\x1b[<24;10;20M"),
+            ),
+            (
+                b"\x1b[?1000h\x1b[?1006h",
+                mouse(Up(MouseButton::Middle), NONE),
+                (0, 0),
+                Some(b"\x1b[<1;1;1m"),
+            ),
+            (
+                b"\x1b[?1003h\x1b[?1006h",
+                mouse(ScrollDown, NONE),
+                (500, 400),
+                Some(b"\x1b[<65;501;401M"),
+            ),
+        ];
+
+        for (modes, event, (col, row), expected) in cases {
+            let mut parser = vt100::Parser::new(24, 80, 0);
+            parser.process(modes);
+            assert_eq!(
+                MouseEncoder::new(parser.screen())
+                    .encode(event, *col, *row)
+                    .as_deref(),
+                *expected,
+                "{:?} at {},{} with modes {:?}",
+                event,
+                col,
+                row,
+                String::from_utf8_lossy(modes)
+            );
+        }
+    }
+}
@@ -1,273 +1,180 @@
+use crate::app::input::encoder::KeyEncoder;
+use crate::app::input::keymap::{Action, Binding, Keymap};
 use crate::app::lease::Lease;
+use crate::app::scroll;
 
 use bytes::Bytes;
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{KeyEvent, KeyEventKind};
+use std::sync::RwLock;
 use tokio::sync::mpsc::Sender;
+use vt100::Parser;
 
+// Returns the actions only the container can carry out
 pub async fn handle_keyboard_input(
     lease: &mut Lease,
+    keymap: &mut Keymap,
+    encoder: &KeyEncoder,
+    focused: &RwLock<Parser>,
     sender: &Sender<Bytes>,
     key_event: KeyEvent,
     term_size: (u16, u16),
-) -> bool {
-    let x = lease.tenant.rect.x;
-    let y = lease.tenant.rect.y;
-    let width = lease.tenant.rect.width;
-    let height = lease.tenant.rect.height;
-
-    if key_event.code == KeyCode::Home {
-        lease.tenant_visible = !lease.tenant_visible;
-        return false;
-    }
-
-    if key_event.modifiers.contains(KeyModifiers::SHIFT) {
-        match key_event.code {
-            KeyCode::Left => {
-                if lease.tenant_visible {
-                    lease
-                        .tenant
-                        .resize_to(x, y, width.saturating_sub(1), height, term_size);
-                    lease.resize_screen(height, width.saturating_sub(1)).await;
-                    return false;
-                } else {
-                    // Send Shift+Left escape sequence
-                    sender
-                        .send(Bytes::from(b"\x1b[1;2D".to_vec()))
-                        .await
-                        .unwrap();
-                    return false;
-                }
-            }
-            KeyCode::Right => {
-                if lease.tenant_visible {
-                    lease.tenant.resize_to(x, y, width + 1, height, term_size);
-                    lease.resize_screen(height, width + 1).await;
-                    return false;
-                } else {
-                    // Send Shift+Right escape sequence
-                    sender
-                        .send(Bytes::from(b"\x1b[1;2C".to_vec()))
-                        .await
-                        .unwrap();
-                    return false;
-                }
-            }
-            KeyCode::Up => {
-                if lease.tenant_visible {
-                    lease
-                        .tenant
-                        .resize_to(x, y, width, height.saturating_sub(1), term_size);
-                    lease.resize_screen(height.saturating_sub(1), width).await;
-                    return false;
-                } else {
-                    // Send Shift+Up escape sequence
-                    sender
-                        .send(Bytes::from(b"\x1b[1;2A".to_vec()))
-                        .await
-                        .unwrap();
-                    return false;
-                }
-            }
-            KeyCode::Down => {
-                if lease.tenant_visible {
-                    lease.tenant.resize_to(x, y, width, height + 1, term_size);
-                    lease.resize_screen(height + 1, width).await;
-                    return false;
-                } else {
-                    // Send Shift+Down escape sequence
-                    sender
-                        .send(Bytes::from(b"\x1b[1;2B".to_vec()))
-                        .await
-                        .unwrap();
-                    return false;
-                }
-            }
-            _ => {} // Pass other keys through
-        }
-    } else if key_event.modifiers.contains(KeyModifiers::CONTROL) {
-        match key_event.code {
-            KeyCode::Left => {
-                if lease.tenant_visible {
-                    lease.tenant.move_to(x.saturating_sub(1), y, term_size);
-                    return false;
-                } else {
-                    // Send Ctrl+Left escape sequence
-                    sender
-                        .send(Bytes::from(b"\x1b[1;5D".to_vec()))
-                        .await
-                        .unwrap();
-                    return false;
-                }
+) -> Option<Action> {
+    // A key uncl took keeps its repeats and release from kitty-aware apps
+    if key_event.kind != KeyEventKind::Press {
+        match keymap.follow(&key_event) {
+            Binding::Action(action) => {
+                return perform_action(lease, action, focused, term_size).await;
             }
-            KeyCode::Right => {
-                if lease.tenant_visible {
-                    lease.tenant.move_to(x + 1, y, term_size);
-                    return false;
-                } else {
-                    // Send Ctrl+Right escape sequence
-                    sender
-                        .send(Bytes::from(b"\x1b[1;5C".to_vec()))
-                        .await
-                        .unwrap();
-                    return false;
-                }
-            }
-            KeyCode::Up => {
-                if lease.tenant_visible {
-                    lease.tenant.move_to(x, y.saturating_sub(1), term_size);
-                    return false;
-                } else {
-                    // Send Ctrl+Up escape sequence
-                    sender
-                        .send(Bytes::from(b"\x1b[1;5A".to_vec()))
-                        .await
-                        .unwrap();
-                    return false;
-                }
-            }
-            KeyCode::Down => {
-                if lease.tenant_visible {
-                    lease.tenant.move_to(x, y + 1, term_size);
-                    return false;
-                } else {
-                    // Send Ctrl+Down escape sequence
-                    sender
-                        .send(Bytes::from(b"\x1b[1;5B".to_vec()))
-                        .await
-                        .unwrap();
-                    return false;
-                }
-            }
-            _ => {} // Pass other control keys through to the application
+            Binding::Consumed => return None,
+            Binding::Forward => {}
         }
     }
 
-    // Handle regular characters
-    match key_event.code {
-        KeyCode::Char(c) => {
-            if key_event.modifiers.contains(KeyModifiers::CONTROL) {
-                // Handle control characters (ASCII 1-26)
-                let ctrl_char = (c as u8) & 0x1F;
-                //TODO: try sync send
-                sender.send(Bytes::from(vec![ctrl_char])).await.unwrap();
-            } else if key_event.modifiers.contains(KeyModifiers::ALT) {
-                sender.send(Bytes::from(vec![27, c as u8])).await.unwrap();
-            } else {
-                // Regular character
-                sender
-                    .send(Bytes::from(c.to_string().into_bytes()))
-                    .await
-                    .unwrap();
+    // Bindings act on presses, releases only matter to kitty-aware apps
+    if key_event.kind != KeyEventKind::Release {
+        // An app on the alternate screen has no history, it gets the keys
+        let no_history = focused.read().unwrap().screen().alternate_screen();
+        match keymap.resolve(&key_event) {
+            Binding::Action(action) if action.scrolls() && no_history => {}
+            Binding::Action(action) if lease.tenant_visible || !action.needs_overlay() => {
+                keymap.hold(&key_event, Some(action));
+                return perform_action(lease, action, focused, term_size).await;
             }
+            // Without a leader these are ordinary keys for the owner
+            Binding::Action(_) if !keymap.has_prefix() => {}
+            Binding::Action(_) | Binding::Consumed => {
+                keymap.hold(&key_event, None);
+                return None;
+            }
+            Binding::Forward => {}
         }
+    }
 
-        KeyCode::Enter => {
-            sender.send(Bytes::from(vec![b'\r'])).await.unwrap();
-        }
-
-        KeyCode::Backspace => {
-            sender.send(Bytes::from(vec![8])).await.unwrap();
-        }
-
-        KeyCode::Delete => {
-            // Send the standard escape sequence for Delete key
-            sender
-                .send(Bytes::from(vec![27, 91, 51, 126]))
-                .await
-                .unwrap();
-        }
-
-        KeyCode::Tab => {
-            sender.send(Bytes::from(vec![9])).await.unwrap();
-        }
-
-        KeyCode::BackTab => {
-            sender.send(Bytes::from(vec![27, 91, 90])).await.unwrap();
-        }
-
-        KeyCode::Left => {
-            sender.send(Bytes::from(vec![27, 91, 68])).await.unwrap();
-        }
-
-        KeyCode::Right => {
-            sender.send(Bytes::from(vec![27, 91, 67])).await.unwrap();
-        }
-
-        KeyCode::Up => {
-            sender.send(Bytes::from(vec![27, 91, 65])).await.unwrap();
-        }
+    if let Some(bytes) = encoder.encode(&key_event) {
+        // Typing while looking at history brings the live screen back
+        scroll::to_live(focused);
+        sender.send(Bytes::from(bytes)).await.unwrap();
+    }
 
-        KeyCode::Down => {
-            sender.send(Bytes::from(vec![27, 91, 66])).await.unwrap();
-        }
+    None
+}
 
-        KeyCode::Esc => {
-            sender.send(Bytes::from(vec![27])).await.unwrap();
-        }
+async fn perform_action(
+    lease: &mut Lease,
+    action: Action,
+    focused: &RwLock<Parser>,
+    term_size: (u16, u16),
+) -> Option<Action> {
+    let x = lease.tenant.rect.x;
+    let y = lease.tenant.rect.y;
+    let width = lease.tenant.rect.width;
+    let height = lease.tenant.rect.height;
 
-        KeyCode::End => {
-            sender.send(Bytes::from(vec![27, 91, 70])).await.unwrap();
+    match action {
+        Action::Toggle => lease.tenant_visible = !lease.tenant_visible,
+        Action::MoveLeft => lease.tenant.move_to(x.saturating_sub(1), y, term_size),
+        Action::MoveRight => lease.tenant.move_to(x + 1, y, term_size),
+        Action::MoveUp => lease.tenant.move_to(x, y.saturating_sub(1), term_size),
+        Action::MoveDown => lease.tenant.move_to(x, y + 1, term_size),
+        Action::ShrinkWidth => {
+            lease
+                .tenant
+                .resize_to(x, y, width.saturating_sub(1), height, term_size);
+        }
+        Action::GrowWidth => lease.tenant.resize_to(x, y, width + 1, height, term_size),
+        Action::ShrinkHeight => {
+            lease
+                .tenant
+                .resize_to(x, y, width, height.saturating_sub(1), term_size);
+        }
+        Action::GrowHeight => lease.tenant.resize_to(x, y, width, height + 1, term_size),
+        Action::Maximize => lease.tenant.toggle_maximized(term_size),
+        Action::Center => lease.tenant.center(term_size),
+        Action::ScrollUp => scroll::scroll_page(focused, true),
+        Action::ScrollDown => scroll::scroll_page(focused, false),
+        Action::RestartTenant => lease.restart(),
+        // These belong to the container
+        Action::CopyMode | Action::Search | Action::Hints | Action::Quit => {
+            return Some(action);
         }
+    }
 
-        KeyCode::PageUp => {
-            sender
-                .send(Bytes::from(vec![27, 91, 53, 126]))
-                .await
-                .unwrap();
-        }
+    // Let the tenant PTY follow whatever the window ended up as
+    let rect = lease.tenant.rect;
+    if rect.width != width || rect.height != height {
+        lease.resize_screen(rect.height, rect.width).await;
+    }
 
-        KeyCode::PageDown => {
-            sender
-                .send(Bytes::from(vec![27, 91, 54, 126]))
-                .await
-                .unwrap();
-        }
+    None
+}
 
-        KeyCode::F(n) => {
-            // Function keys
-            match n {
-                1 => sender.send(Bytes::from(vec![27, 79, 80])).await.unwrap(),
-                2 => sender.send(Bytes::from(vec![27, 79, 81])).await.unwrap(),
-                3 => sender.send(Bytes::from(vec![27, 79, 82])).await.unwrap(),
-                4 => sender.send(Bytes::from(vec![27, 79, 83])).await.unwrap(),
-                5 => sender
-                    .send(Bytes::from(vec![27, 91, 49, 53, 126]))
-                    .await
-                    .unwrap(),
-                6 => sender
-                    .send(Bytes::from(vec![27, 91, 49, 55, 126]))
-                    .await
-                    .unwrap(),
-                7 => sender
-                    .send(Bytes::from(vec![27, 91, 49, 56, 126]))
-                    .await
-                    .unwrap(),
-                8 => sender
-                    .send(Bytes::from(vec![27, 91, 49, 57, 126]))
-                    .await
-                    .unwrap(),
-                9 => sender
-                    .send(Bytes::from(vec![27, 91, 50, 48, 126]))
-                    .await
-                    .unwrap(),
-                10 => sender
-                    .send(Bytes::from(vec![27, 91, 50, 49, 126]))
-                    .await
-                    .unwrap(),
-                11 => sender
-                    .send(Bytes::from(vec![27, 91, 50, 51, 126]))
-                    .await
-                    .unwrap(),
-                12 => sender
-                    .send(Bytes::from(vec![27, 91, 50, 52, 126]))
-                    .await
-                    .unwrap(),
-                _ => {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crossterm::event::{KeyCode, KeyModifiers};
+    use tokio::sync::mpsc::channel;
+
+    use crate::app::input::keymap::KeysConfig;
+    use crate::app::tap::gate::Gate;
+    use crate::app::tap::kitty::{DISAMBIGUATE, REPORT_EVENTS};
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn keeps_held_keys_from_kitty_apps() {
+        let config = Arc::new(Config::default());
+        let mut lease = Lease::new(config, Arc::new(Gate::default()));
+        lease.tenant_visible = true;
+        let keys: KeysConfig = toml::from_str("prefix = \"ctrl+a\"").unwrap();
+        let mut keymap = Keymap::new(&keys).unwrap();
+        let focused = RwLock::new(Parser::new(5, 20, 0));
+        let encoder = KeyEncoder::new(
+            focused.read().unwrap().screen(),
+            DISAMBIGUATE | REPORT_EVENTS,
+        );
+        let (tx, mut rx) = channel(16);
+
+        let x = lease.tenant.rect.x;
+        let (ctrl, none) = (KeyModifiers::CONTROL, KeyModifiers::NONE);
+        let (press, repeat, release) = (
+            KeyEventKind::Press,
+            KeyEventKind::Repeat,
+            KeyEventKind::Release,
+        );
+        let keys = [
+            (KeyCode::Char('a'), ctrl, press),
+            (KeyCode::Char('a'), ctrl, repeat),
+            // Ctrl can come up first
+            (KeyCode::Char('a'), none, release),
+            (KeyCode::Right, ctrl, press),
+            (KeyCode::Right, ctrl, repeat),
+            (KeyCode::Right, ctrl, release),
+            (KeyCode::Left, none, press),
+            (KeyCode::Left, none, release),
+        ];
+        let mut sent = Vec::new();
+        for (code, modifiers, kind) in keys {
+            let mut key_event = KeyEvent::new(code, modifiers);
+            key_event.kind = kind;
+            handle_keyboard_input(
+                &mut lease,
+                &mut keymap,
+                &encoder,
+                &focused,
+                &tx,
+                key_event,
+                (200, 60),
+            )
+            .await;
+            while let Ok(bytes) = rx.try_recv() {
+                sent.push(String::from_utf8_lossy(&bytes).into_owned());
             }
         }
 
-        _ => return false,
+        // The leader arms once, its action repeats, only Left reaches the app
+        assert_eq!(lease.tenant.rect.x, x + 2);
+        assert_eq!(sent, ["\x1b[D", "\x1b[1;1:3D"]);
     }
-
-    false
 }
@@ -0,0 +1,491 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use serde::{
+    Deserialize, Deserializer,
+    de::{self, IntoDeserializer, SeqAccess, Visitor},
+};
+
+use crate::app::input::chord::KeyChord;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Toggle,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    ShrinkWidth,
+    GrowWidth,
+    ShrinkHeight,
+    GrowHeight,
+    Maximize,
+    Center,
+    ScrollUp,
+    ScrollDown,
+    CopyMode,
+    Search,
+    Hints,
+    RestartTenant,
+    Quit,
+}
+
+impl Action {
+    // Window actions only mean something while the overlay is on screen,
+    // otherwise their keys go to the owner like any other key
+    pub fn needs_overlay(&self) -> bool {
+        matches!(
+            self,
+            Action::MoveLeft
+                | Action::MoveRight
+                | Action::MoveUp
+                | Action::MoveDown
+                | Action::ShrinkWidth
+                | Action::GrowWidth
+                | Action::ShrinkHeight
+                | Action::GrowHeight
+                | Action::Maximize
+                | Action::Center
+        )
+    }
+
+    pub fn scrolls(&self) -> bool {
+        matches!(self, Action::ScrollUp | Action::ScrollDown)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Toggle => "toggle",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::ShrinkWidth => "shrink_width",
+            Action::GrowWidth => "grow_width",
+            Action::ShrinkHeight => "shrink_height",
+            Action::GrowHeight => "grow_height",
+            Action::Maximize => "maximize",
+            Action::Center => "center",
+            Action::ScrollUp => "scroll_up",
+            Action::ScrollDown => "scroll_down",
+            Action::CopyMode => "copy_mode",
+            Action::Search => "search",
+            Action::Hints => "hints",
+            Action::RestartTenant => "restart_tenant",
+            Action::Quit => "quit",
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// The `[keys]` table: every action maps to zero or more chords. Actions the
+// user leaves out keep their default binding, `[]` unbinds one. Setting
+// `prefix` turns on leader mode, where every binding needs the leader first.
+#[derive(Debug, Clone)]
+pub struct KeysConfig {
+    pub bindings: HashMap<Action, Vec<KeyChord>>,
+    pub prefix: Option<KeyChord>,
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        use KeyCode::{Char, Down, F, Home, Left, PageDown, PageUp, Right, Up};
+        let (none, ctrl, shift) = (
+            KeyModifiers::NONE,
+            KeyModifiers::CONTROL,
+            KeyModifiers::SHIFT,
+        );
+        let ctrl_alt = ctrl | KeyModifiers::ALT;
+        let bind = |action, code, modifiers| (action, vec![KeyChord::new(code, modifiers)]);
+
+        let bindings = HashMap::from([
+            bind(Action::Toggle, Home, none),
+            bind(Action::MoveLeft, Left, ctrl),
+            bind(Action::MoveRight, Right, ctrl),
+            bind(Action::MoveUp, Up, ctrl),
+            bind(Action::MoveDown, Down, ctrl),
+            bind(Action::ShrinkWidth, Left, shift),
+            bind(Action::GrowWidth, Right, shift),
+            bind(Action::ShrinkHeight, Up, shift),
+            bind(Action::GrowHeight, Down, shift),
+            (
+                Action::Maximize,
+                vec![
+                    KeyChord::new(Char('m'), ctrl_alt),
+                    KeyChord::new(F(11), none),
+                ],
+            ),
+            bind(Action::ScrollUp, PageUp, shift),
+            bind(Action::ScrollDown, PageDown, shift),
+            bind(Action::CopyMode, Char('c'), ctrl_alt),
+            bind(Action::Search, Char('f'), ctrl_alt),
+            bind(Action::Hints, Char('h'), ctrl_alt),
+        ]);
+
+        Self {
+            bindings,
+            prefix: None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for KeysConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let overrides = HashMap::<String, Chords>::deserialize(deserializer)?;
+        let mut keys = Self::default();
+        for (name, Chords(chords)) in overrides {
+            if name == "prefix" {
+                if chords.len() > 1 {
+                    return Err(de::Error::custom("only one prefix key can be set"));
+                }
+                keys.prefix = chords.first().copied();
+                continue;
+            }
+
+            let action = Action::deserialize(name.into_deserializer())?;
+            keys.bindings.insert(action, chords);
+        }
+        Ok(keys)
+    }
+}
+
+// Accepts either `"ctrl+left"` or `["ctrl+left", "alt+h"]`
+struct Chords(Vec<KeyChord>);
+
+impl<'de> Deserialize<'de> for Chords {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ChordsVisitor;
+
+        impl<'de> Visitor<'de> for ChordsVisitor {
+            type Value = Chords;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a key like \"ctrl+left\" or a list of keys")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Chords, E> {
+                Ok(Chords(vec![v.parse().map_err(E::custom)?]))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Chords, A::Error> {
+                let mut chords = Vec::new();
+                while let Some(chord) = seq.next_element::<KeyChord>()? {
+                    chords.push(chord);
+                }
+                Ok(Chords(chords))
+            }
+        }
+
+        deserializer.deserialize_any(ChordsVisitor)
+    }
+}
+
+pub enum Binding {
+    Action(Action),
+    // Swallowed by uncl, e.g. the leader itself
+    Consumed,
+    Forward,
+}
+
+// How long the leader waits for the key it arms
+const PREFIX_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct Keymap {
+    actions: HashMap<KeyChord, Action>,
+    prefix: Option<KeyChord>,
+    armed: Option<Instant>,
+    // Keys whose press uncl took until they're released, with the action
+    // the press carried out if it did one
+    held: HashMap<KeyCode, Option<Action>>,
+}
+
+impl Keymap {
+    pub fn new(keys: &KeysConfig) -> Result<Self> {
+        let mut actions = HashMap::new();
+        for (action, chords) in &keys.bindings {
+            for chord in chords {
+                if let Some(other) = actions.insert(*chord, *action)
+                    && other != *action
+                {
+                    bail!("key `{}` is bound to both {} and {}", chord, other, action);
+                }
+            }
+        }
+
+        if let Some(prefix) = keys.prefix
+            && let Some(action) = actions.get(&prefix)
+        {
+            bail!("prefix key `{}` is also bound to {}", prefix, action);
+        }
+
+        Ok(Self {
+            actions,
+            prefix: keys.prefix,
+            armed: None,
+            held: HashMap::new(),
+        })
+    }
+
+    pub fn has_prefix(&self) -> bool {
+        self.prefix.is_some()
+    }
+
+    pub fn resolve(&mut self, key_event: &KeyEvent) -> Binding {
+        self.resolve_at(key_event, Instant::now())
+    }
+
+    // Remembers a press uncl kept from the app, for `follow`
+    pub fn hold(&mut self, key_event: &KeyEvent, action: Option<Action>) {
+        self.held
+            .insert(KeyChord::from_event(key_event).code, action);
+    }
+
+    // Repeats and the release of a key go where its press went. The repeats
+    // of a held key carry out its action again and nothing of it is sent,
+    // the modifiers may already be up by then so only the key is matched.
+    pub fn follow(&mut self, key_event: &KeyEvent) -> Binding {
+        let code = KeyChord::from_event(key_event).code;
+        let held = match key_event.kind {
+            KeyEventKind::Release => self.held.remove(&code),
+            _ => self.held.get(&code).copied(),
+        };
+        match held {
+            Some(Some(action)) if key_event.kind == KeyEventKind::Repeat => Binding::Action(action),
+            Some(_) => Binding::Consumed,
+            None => Binding::Forward,
+        }
+    }
+
+    fn resolve_at(&mut self, key_event: &KeyEvent, now: Instant) -> Binding {
+        let chord = KeyChord::from_event(key_event);
+        let action = self.actions.get(&chord).copied();
+
+        let Some(prefix) = self.prefix else {
+            return action.map_or(Binding::Forward, Binding::Action);
+        };
+
+        // A leader left hanging lapses, the next key is an ordinary one
+        let armed = self
+            .armed
+            .take()
+            .is_some_and(|at| now.duration_since(at) < PREFIX_TIMEOUT);
+        if !armed {
+            if chord == prefix {
+                self.armed = Some(now);
+                return Binding::Consumed;
+            }
+            return Binding::Forward;
+        }
+
+        // Leader twice sends it through, anything unbound after it is dropped
+        match action {
+            _ if chord == prefix => Binding::Forward,
+            Some(action) => Binding::Action(action),
+            None => Binding::Consumed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(raw: &str) -> KeysConfig {
+        toml::from_str(raw).unwrap()
+    }
+
+    fn chord(s: &str) -> KeyChord {
+        s.parse().unwrap()
+    }
+
+    fn lookup(keymap: &mut Keymap, s: &str) -> Option<Action> {
+        let chord = chord(s);
+        match keymap.resolve(&KeyEvent::new(chord.code, chord.modifiers)) {
+            Binding::Action(action) => Some(action),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn defaults_match_the_readme() {
+        let readme = include_str!("../../../readme.md");
+        let (_, example) = readme.split_once("```toml").unwrap();
+        let (example, _) = example.split_once("```").unwrap();
+        let config: toml::Table = toml::from_str(example).unwrap();
+        let documented: KeysConfig = config["keys"].clone().try_into().unwrap();
+
+        // `[]` and leaving an action out both mean unbound
+        let bound = |keys: KeysConfig| -> HashMap<_, _> {
+            keys.bindings
+                .into_iter()
+                .filter(|(_, c)| !c.is_empty())
+                .collect()
+        };
+        assert_eq!(bound(documented.clone()), bound(KeysConfig::default()));
+        assert_eq!(documented.prefix, None);
+    }
+
+    #[test]
+    fn looks_up_default_bindings() {
+        let mut keymap = Keymap::new(&KeysConfig::default()).unwrap();
+        let cases = [
+            ("home", Some(Action::Toggle)),
+            ("ctrl+left", Some(Action::MoveLeft)),
+            ("shift+down", Some(Action::GrowHeight)),
+            ("ctrl+alt+m", Some(Action::Maximize)),
+            ("f11", Some(Action::Maximize)),
+            ("shift+pageup", Some(Action::ScrollUp)),
+            ("ctrl+alt+h", Some(Action::Hints)),
+            ("left", None),
+            ("ctrl+m", None),
+            ("f12", None),
+        ];
+        for (key, expected) in cases {
+            assert_eq!(lookup(&mut keymap, key), expected, "{}", key);
+        }
+    }
+
+    #[test]
+    fn overrides_and_unbinds() {
+        let keys = keys(
+            r#"
+            toggle = ["f12", "alt+t"]
+            maximize = []
+            quit = "ctrl+alt+q"
+            "#,
+        );
+        let mut keymap = Keymap::new(&keys).unwrap();
+
+        assert_eq!(lookup(&mut keymap, "f12"), Some(Action::Toggle));
+        assert_eq!(lookup(&mut keymap, "alt+t"), Some(Action::Toggle));
+        assert_eq!(lookup(&mut keymap, "home"), None);
+        assert_eq!(lookup(&mut keymap, "f11"), None);
+        assert_eq!(lookup(&mut keymap, "ctrl+alt+q"), Some(Action::Quit));
+        // Untouched actions keep their defaults
+        assert_eq!(lookup(&mut keymap, "ctrl+left"), Some(Action::MoveLeft));
+    }
+
+    fn prefixed(prefix: &str) -> Keymap {
+        Keymap::new(&keys(&format!("prefix = \"{}\"", prefix))).unwrap()
+    }
+
+    fn resolve(keymap: &mut Keymap, s: &str, now: Instant) -> Binding {
+        let chord = chord(s);
+        keymap.resolve_at(&KeyEvent::new(chord.code, chord.modifiers), now)
+    }
+
+    #[test]
+    fn prefix_arms_the_next_key() {
+        let mut keymap = prefixed("ctrl+a");
+        let now = Instant::now();
+
+        // Bindings are plain keys until the leader comes first
+        assert!(matches!(
+            resolve(&mut keymap, "ctrl+left", now),
+            Binding::Forward
+        ));
+        assert!(matches!(
+            resolve(&mut keymap, "ctrl+a", now),
+            Binding::Consumed
+        ));
+        assert!(matches!(
+            resolve(&mut keymap, "ctrl+left", now),
+            Binding::Action(Action::MoveLeft)
+        ));
+        // One leader arms one key
+        assert!(matches!(
+            resolve(&mut keymap, "ctrl+left", now),
+            Binding::Forward
+        ));
+    }
+
+    #[test]
+    fn prefix_twice_is_sent_literally() {
+        let mut keymap = prefixed("ctrl+a");
+        let now = Instant::now();
+
+        assert!(matches!(
+            resolve(&mut keymap, "ctrl+a", now),
+            Binding::Consumed
+        ));
+        assert!(matches!(
+            resolve(&mut keymap, "ctrl+a", now),
+            Binding::Forward
+        ));
+        // and doesn't leave the leader armed
+        assert!(matches!(
+            resolve(&mut keymap, "home", now),
+            Binding::Forward
+        ));
+    }
+
+    #[test]
+    fn prefix_then_unbound_key_is_dropped() {
+        let mut keymap = prefixed("ctrl+a");
+        let now = Instant::now();
+
+        assert!(matches!(
+            resolve(&mut keymap, "ctrl+a", now),
+            Binding::Consumed
+        ));
+        assert!(matches!(resolve(&mut keymap, "x", now), Binding::Consumed));
+        assert!(matches!(resolve(&mut keymap, "x", now), Binding::Forward));
+    }
+
+    #[test]
+    fn prefix_times_out() {
+        let mut keymap = prefixed("ctrl+a");
+        let now = Instant::now();
+        let late = now + PREFIX_TIMEOUT;
+
+        assert!(matches!(
+            resolve(&mut keymap, "ctrl+a", now),
+            Binding::Consumed
+        ));
+        assert!(matches!(
+            resolve(&mut keymap, "home", late),
+            Binding::Forward
+        ));
+
+        // A lapsed leader followed by the leader arms afresh
+        assert!(matches!(
+            resolve(&mut keymap, "ctrl+a", now),
+            Binding::Consumed
+        ));
+        assert!(matches!(
+            resolve(&mut keymap, "ctrl+a", late),
+            Binding::Consumed
+        ));
+        assert!(matches!(
+            resolve(&mut keymap, "home", late + PREFIX_TIMEOUT / 2),
+            Binding::Action(Action::Toggle)
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_tables() {
+        assert!(toml::from_str::<KeysConfig>("nope = \"f1\"").is_err());
+        assert!(toml::from_str::<KeysConfig>("toggle = \"ctrl+nope\"").is_err());
+        assert!(toml::from_str::<KeysConfig>("prefix = [\"ctrl+a\", \"ctrl+b\"]").is_err());
+    }
+
+    #[test]
+    fn rejects_conflicting_bindings() {
+        let err = Keymap::new(&keys("quit = \"home\"")).err().unwrap();
+        assert!(err.to_string().contains("`home`"), "{}", err);
+
+        let err = Keymap::new(&keys("prefix = \"f11\"")).err().unwrap();
+        assert!(err.to_string().contains("prefix key `f11`"), "{}", err);
+    }
+}
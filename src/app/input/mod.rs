@@ -1,2 +1,6 @@
+pub mod chord;
+pub mod encoder;
 pub mod keyboard;
+pub mod keymap;
 pub mod mouse;
+pub mod paste;
@@ -1,11 +1,44 @@
+use crate::app::copy::Pane;
+use crate::app::copy::hints;
+use crate::app::copy::mouse::MouseSelect;
+use crate::app::input::encoder::MouseEncoder;
 use crate::app::lease::Lease;
-use crate::constants::{MIN_HEIGHT, MIN_WIDTH, ResizeDirection};
-use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use crate::app::scroll::{self, WHEEL_LINES};
+use crate::app::tap::{PtyState, links};
+use crate::constants::ResizeDirection;
+use bytes::Bytes;
+use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
 use ratatui::layout::Rect;
+use std::sync::RwLock;
+use tokio::sync::mpsc::Sender;
+use vt100::Parser;
+
+pub async fn handle_mouse(
+    lease: &mut Lease,
+    select: &mut MouseSelect,
+    m: MouseEvent,
+    bounds: (u16, u16),
+) {
+    if open_link(lease, m) || forward_to_tenant(lease, m).await {
+        return;
+    }
+
+    // The tenant screen itself selects text, the frame around it moves and
+    // resizes the window
+    let content = lease.tenant.content_rect();
+    if !lease.tenant.dragging
+        && !lease.tenant.resizing
+        && select.handle(Pane::Tenant, &lease.tenant_parser, content, &m)
+    {
+        return;
+    }
 
-pub async fn handle_mouse(lease: &mut Lease, m: MouseEvent, bounds: (u16, u16)) {
     let overlay = &mut lease.tenant;
     let rect = overlay.rect;
+    let (min_width, min_height) = (
+        overlay.config.window.min_width,
+        overlay.config.window.min_height,
+    );
     let x = m.column;
     let y = m.row;
 
@@ -48,35 +81,35 @@ pub async fn handle_mouse(lease: &mut Lease, m: MouseEvent, bounds: (u16, u16))
                     let rect = overlay.rect;
                     let (new_x, new_y, new_width, new_height) = match direction {
                         ResizeDirection::TopLeft => {
-                            let new_x = m.column.min(rect.x + rect.width - MIN_WIDTH).max(0);
-                            let new_y = m.row.min(rect.y + rect.height - MIN_HEIGHT).max(0);
-                            let new_width = (rect.x + rect.width - new_x).max(MIN_WIDTH); // Clamping to MIN_WIDTH
-                            let new_height = (rect.y + rect.height - new_y).max(MIN_HEIGHT); // Clamping to MIN_HEIGHT
+                            let new_x = m.column.min(rect.x + rect.width - min_width);
+                            let new_y = m.row.min(rect.y + rect.height - min_height);
+                            let new_width = (rect.x + rect.width - new_x).max(min_width); // Clamping to min_width
+                            let new_height = (rect.y + rect.height - new_y).max(min_height); // Clamping to min_height
                             (new_x, new_y, new_width, new_height)
                         }
 
                         ResizeDirection::TopRight => {
-                            let new_y = m.row.min(rect.y + rect.height - MIN_HEIGHT).max(0);
-                            let new_width = (m.column.saturating_sub(rect.x)).max(MIN_WIDTH);
-                            let new_height = (rect.y + rect.height - new_y).max(MIN_HEIGHT);
+                            let new_y = m.row.min(rect.y + rect.height - min_height);
+                            let new_width = (m.column.saturating_sub(rect.x)).max(min_width);
+                            let new_height = (rect.y + rect.height - new_y).max(min_height);
                             (rect.x, new_y, new_width, new_height)
                         }
 
                         ResizeDirection::BottomLeft => {
-                            let new_x = m.column.min(rect.x + rect.width - MIN_WIDTH).max(0);
-                            let new_width = (rect.x + rect.width - new_x).max(MIN_WIDTH);
-                            let new_height = (m.row.saturating_sub(rect.y)).max(MIN_HEIGHT);
+                            let new_x = m.column.min(rect.x + rect.width - min_width);
+                            let new_width = (rect.x + rect.width - new_x).max(min_width);
+                            let new_height = (m.row.saturating_sub(rect.y)).max(min_height);
                             (new_x, rect.y, new_width, new_height)
                         }
 
                         ResizeDirection::BottomRight => {
-                            let new_width = (m.column.saturating_sub(rect.x)).max(MIN_WIDTH);
-                            let new_height = (m.row.saturating_sub(rect.y)).max(MIN_HEIGHT);
+                            let new_width = (m.column.saturating_sub(rect.x)).max(min_width);
+                            let new_height = (m.row.saturating_sub(rect.y)).max(min_height);
                             (rect.x, rect.y, new_width, new_height)
                         }
                     };
 
-                    if new_width >= MIN_WIDTH && new_height >= MIN_HEIGHT {
+                    if new_width >= min_width && new_height >= min_height {
                         overlay.resize_to(new_x, new_y, new_width, new_height, bounds);
                         lease.resize_screen(new_height, new_width).await;
                     }
@@ -97,12 +130,189 @@ pub async fn handle_mouse(lease: &mut Lease, m: MouseEvent, bounds: (u16, u16))
             overlay.resize_direction = None;
         }
 
+        // The wheel pages through history unless the tenant app wants it
+        MouseEventKind::ScrollUp if is_within_overlay(m, rect) => {
+            scroll::scroll_by(&lease.tenant_parser, WHEEL_LINES);
+        }
+        MouseEventKind::ScrollDown if is_within_overlay(m, rect) => {
+            scroll::scroll_by(&lease.tenant_parser, -WHEEL_LINES);
+        }
+
         _ => {}
     }
 }
 
+// Ctrl+click on an OSC 8 link in the tenant screen hands its target to the
+// opener, before the app inside can have the click
+fn open_link(lease: &Lease, m: MouseEvent) -> bool {
+    let content = lease.tenant.content_rect();
+    open_link_at(
+        &lease.tenant_parser,
+        &lease.tenant_state,
+        content,
+        hints::opener(&lease.config.links.opener),
+        m,
+    )
+}
+
+// `open` is handed the link's target, without it links are left to the app
+fn open_link_at(
+    parser: &RwLock<Parser>,
+    state: &RwLock<PtyState>,
+    area: Rect,
+    open: Option<impl FnOnce(&str)>,
+    m: MouseEvent,
+) -> bool {
+    let Some(open) = open.filter(|_| {
+        m.kind == MouseEventKind::Down(MouseButton::Left)
+            && m.modifiers.contains(KeyModifiers::CONTROL)
+            && area.contains((m.column, m.row).into())
+    }) else {
+        return false;
+    };
+
+    let parser = parser.read().unwrap();
+    let state = state.read().unwrap();
+    let (row, col) = (m.row - area.y, m.column - area.x);
+    match state.links.at(parser.screen(), row, col) {
+        Some(link) => {
+            open(links::target(link));
+            true
+        }
+        None => false,
+    }
+}
+
+// Events over the tenant screen go to the app inside when it asked for mouse
+// reports, the border and handles keep moving and resizing the window
+async fn forward_to_tenant(lease: &mut Lease, m: MouseEvent) -> bool {
+    let overlay = &mut lease.tenant;
+    // Shift gets the mouse back from the app for selecting
+    if overlay.dragging || overlay.resizing || m.modifiers.contains(KeyModifiers::SHIFT) {
+        return false;
+    }
+
+    let encoder = MouseEncoder::new(lease.tenant_parser.read().unwrap().screen());
+    let content = overlay.content_rect();
+    let inside = is_within_overlay(m, content);
+    if !encoder.enabled() || content.is_empty() || !(inside || overlay.mouse_grabbed) {
+        overlay.mouse_grabbed = false;
+        return false;
+    }
+
+    match m.kind {
+        MouseEventKind::Down(_) => overlay.mouse_grabbed = true,
+        MouseEventKind::Up(_) => overlay.mouse_grabbed = false,
+        _ => {}
+    }
+
+    // A grabbed drag that leaves the window sticks to its edge
+    let col = m.column.clamp(content.left(), content.right() - 1) - content.left();
+    let row = m.row.clamp(content.top(), content.bottom() - 1) - content.top();
+    if let Some(bytes) = encoder.encode(&m, col, row)
+        && let Err(e) = lease.tenant_tx.send(Bytes::from(bytes)).await
+    {
+        eprintln!("Failed to send mouse event: {}", e);
+    }
+
+    true
+}
+
+// The owner fills the host, so host cells are already owner cells. Ctrl+click
+// opens links as in the tenant screen. When the owner app doesn't want the
+// mouse, or Shift is held, the mouse selects text and the wheel scrolls the
+// history.
+pub fn handle_owner_mouse(
+    parser: &RwLock<Parser>,
+    state: &RwLock<PtyState>,
+    select: &mut MouseSelect,
+    sender: &Sender<Bytes>,
+    area: Rect,
+    open: Option<impl FnOnce(&str)>,
+    m: MouseEvent,
+) {
+    if open_link_at(parser, state, area, open, m) {
+        return;
+    }
+
+    let encoder = MouseEncoder::new(parser.read().unwrap().screen());
+    if !encoder.enabled() || m.modifiers.contains(KeyModifiers::SHIFT) || select.selecting() {
+        if select.handle(Pane::Owner, parser, area, &m) {
+            return;
+        }
+        match m.kind {
+            MouseEventKind::ScrollUp => scroll::scroll_by(parser, WHEEL_LINES),
+            MouseEventKind::ScrollDown => scroll::scroll_by(parser, -WHEEL_LINES),
+            _ => {}
+        }
+        return;
+    }
+
+    if let Some(bytes) = encoder.encode(&m, m.column, m.row)
+        && let Err(e) = sender.try_send(Bytes::from(bytes))
+    {
+        eprintln!("Failed to send mouse event: {}", e);
+    }
+}
+
 pub fn is_within_overlay(m: MouseEvent, r: Rect) -> bool {
     let x = m.column;
     let y = m.row;
     x >= r.x && x < r.x + r.width && y >= r.y && y < r.y + r.height
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::sync::mpsc::channel;
+
+    // An owner screen with mouse reports on and "go" linked at (0, 3)
+    fn owner() -> (RwLock<Parser>, RwLock<PtyState>) {
+        let mut parser = Parser::new(5, 20, 0);
+        let mut state = PtyState::default();
+        parser.process(b"\x1b[?1000hab ");
+        state.links.set(b";http://x");
+        for byte in *b"go" {
+            parser.process(&[byte]);
+            if let Some(tag) = state.links.tag_after(byte) {
+                parser.process(tag);
+            }
+        }
+        state.links.set(b";");
+        (RwLock::new(parser), RwLock::new(state))
+    }
+
+    fn click(column: u16, modifiers: KeyModifiers) -> MouseEvent {
+        MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column,
+            row: 0,
+            modifiers,
+        }
+    }
+
+    #[tokio::test]
+    async fn ctrl_click_opens_owner_links() {
+        let (parser, state) = owner();
+        let mut select = MouseSelect::default();
+        let (tx, mut rx) = channel(16);
+        let area = Rect::new(0, 0, 20, 5);
+        let mut opened = Vec::new();
+        let mut sent = |m: MouseEvent, opener: bool| {
+            let open = |target: &str| opened.push(target.to_string());
+            let open = opener.then_some(open);
+            handle_owner_mouse(&parser, &state, &mut select, &tx, area, open, m);
+            rx.try_recv().is_ok()
+        };
+
+        // The link takes the click, the app gets the rest
+        assert!(!sent(click(3, KeyModifiers::CONTROL), true));
+        assert!(!sent(click(4, KeyModifiers::CONTROL), true));
+        assert!(sent(click(1, KeyModifiers::CONTROL), true));
+        assert!(sent(click(3, KeyModifiers::NONE), true));
+        // Without an opener links are left to the app
+        assert!(sent(click(3, KeyModifiers::CONTROL), false));
+        assert_eq!(opened, ["http://x", "http://x"]);
+    }
+}
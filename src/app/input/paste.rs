@@ -0,0 +1,119 @@
+use std::io::{self, Write};
+
+use bytes::Bytes;
+use tokio::sync::mpsc::Sender;
+
+const PASTE_START: &str = "\x1b[200~";
+const PASTE_END: &str = "\x1b[201~";
+
+// Big pastes go out in pieces so the PTY writer never sees one huge write
+const CHUNK_SIZE: usize = 4096;
+
+// The paste is queued as one message, so keys and focus reports sent after
+// it can't land inside the bracket and two pastes can't interleave. It's
+// dropped rather than waited on when the PTY is that far behind, so the UI
+// never stalls on it.
+pub fn handle_paste(sender: &Sender<Bytes>, bracketed: bool, text: String) {
+    if let Err(e) = sender.try_send(paste_bytes(bracketed, text)) {
+        eprintln!("Failed to send paste: {}", e);
+    }
+}
+
+fn paste_bytes(bracketed: bool, text: String) -> Bytes {
+    // Terminals send Enter as CR, do the same for pasted line breaks
+    let text = text.replace("\r\n", "\r").replace('\n', "\r");
+
+    if bracketed {
+        // Without ESC no end marker can be spelled out, however it's nested,
+        // and the other controls have no business in pasted text either
+        let text: String = text
+            .chars()
+            .filter(|&c| !c.is_control() || c == '\t' || c == '\r')
+            .collect();
+        format!("{}{}{}", PASTE_START, text, PASTE_END).into()
+    } else {
+        text.into()
+    }
+}
+
+// Used by the PTY writer tasks for everything they're sent
+pub fn write_chunked(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    for chunk in bytes.chunks(CHUNK_SIZE) {
+        writer.write_all(chunk)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::sync::mpsc::channel;
+
+    #[tokio::test]
+    async fn queues_the_paste_whole() {
+        let (tx, mut rx) = channel(4);
+        let text = format!(
+            "{}\n{}\x1b[201~rm -rf ~\n\x1b[20\x1b[201~1~\x07\tz",
+            "x".repeat(CHUNK_SIZE * 3),
+            "y"
+        );
+        handle_paste(&tx, true, text);
+        tx.send(Bytes::from_static(b"\r")).await.unwrap();
+
+        let paste = rx.recv().await.unwrap();
+        let inner = paste
+            .strip_prefix(PASTE_START.as_bytes())
+            .and_then(|p| p.strip_suffix(PASTE_END.as_bytes()))
+            .expect("paste is bracketed");
+        assert!(!inner.contains(&0x1b));
+        assert!(!inner.contains(&b'\n'));
+        assert!(inner.ends_with(b"rm -rf ~\r[20[201~1~\tz"));
+        assert_eq!(
+            inner.len(),
+            CHUNK_SIZE * 3 + "\ry[201~rm -rf ~\r[20[201~1~\tz".len()
+        );
+        // The next key comes after the whole paste
+        assert_eq!(rx.recv().await.unwrap(), "\r");
+    }
+
+    #[tokio::test]
+    async fn leaves_unbracketed_pastes_bare() {
+        let (tx, mut rx) = channel(4);
+        handle_paste(&tx, false, "a\r\nb\n".to_string());
+        assert_eq!(rx.recv().await.unwrap(), "a\rb\r");
+    }
+
+    #[tokio::test]
+    async fn drops_the_paste_when_the_pty_is_behind() {
+        let (tx, mut rx) = channel(1);
+        tx.send(Bytes::from_static(b"a")).await.unwrap();
+
+        // Returns straight away instead of waiting for room
+        handle_paste(&tx, true, "b".to_string());
+        assert_eq!(rx.recv().await.unwrap(), "a");
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn writes_in_chunks() {
+        struct Writes(Vec<usize>, Vec<u8>);
+        impl Write for Writes {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.push(buf.len());
+                self.1.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let bytes: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let mut writes = Writes(Vec::new(), Vec::new());
+        write_chunked(&mut writes, &bytes).unwrap();
+        assert_eq!(writes.0, [CHUNK_SIZE, CHUNK_SIZE, 10]);
+        assert_eq!(writes.1, bytes);
+    }
+}
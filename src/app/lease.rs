@@ -1,44 +1,55 @@
+use crate::app::scroll::history_len;
+use crate::app::tap::{PtyState, gate::Gate};
 use crate::app::ui::tenant::Overlay;
+use crate::config::Config;
 use crate::constants::*;
 use bytes::Bytes;
 
+use portable_pty::ChildKiller;
 use std::sync::{Arc, RwLock};
 use tokio::sync::mpsc::{Receiver, Sender, channel};
 
 pub struct Lease {
     pub tenant: Overlay,
     pub tenant_parser: Arc<RwLock<vt100::Parser>>,
+    pub tenant_state: Arc<RwLock<PtyState>>,
     pub tenant_visible: bool,
     pub tenant_tx: Sender<Bytes>,
     pub tenant_rx: Option<Receiver<Bytes>>,
     pub tenant_status_tx: Sender<bool>,
     pub tenant_status_rx: Receiver<bool>,
     pub tenant_resize_tx: Option<Sender<(u16, u16)>>,
+    pub tenant_killer: Option<Box<dyn ChildKiller + Send + Sync>>,
+    // Shared with the container, paces the tenant's reader
+    pub gate: Arc<Gate>,
+    pub config: Arc<Config>,
 }
 
 impl Lease {
-    pub fn new() -> Self {
+    pub fn new(config: Arc<Config>, gate: Arc<Gate>) -> Self {
         let (ttx, trx) = channel::<Bytes>(32);
         let (tpty_status_tx, tpty_status_rx) = channel::<bool>(1);
 
         let tparser = Arc::new(RwLock::new(vt100::Parser::new(
-            DEFAULT_HEIGHT,
-            DEFAULT_WIDTH,
-            0,
+            config.window.height - OVERLAY_INSET,
+            config.window.width - OVERLAY_INSET,
+            config.scrollback.tenant,
         )));
 
-        let lease = Lease {
+        Lease {
             tenant_visible: false,
-            tenant: Overlay::new(),
+            tenant: Overlay::new(config.clone()),
             tenant_parser: tparser,
+            tenant_state: Arc::new(RwLock::new(PtyState::default())),
             tenant_tx: ttx,
             tenant_rx: Some(trx),
             tenant_status_tx: tpty_status_tx,
             tenant_status_rx: tpty_status_rx,
             tenant_resize_tx: None,
-        };
-
-        lease
+            tenant_killer: None,
+            gate,
+            config,
+        }
     }
 
     pub fn expired(&mut self) -> bool {
@@ -55,35 +66,61 @@ impl Lease {
         let (tpty_status_tx, tpty_status_rx) = channel::<bool>(1);
 
         let tparser = Arc::new(RwLock::new(vt100::Parser::new(
-            DEFAULT_HEIGHT,
-            DEFAULT_WIDTH,
-            0,
+            self.config.window.height - OVERLAY_INSET,
+            self.config.window.width - OVERLAY_INSET,
+            self.config.scrollback.tenant,
         )));
 
         Lease {
             tenant_visible: false,
-            tenant: Overlay::new(),
+            tenant: Overlay::new(self.config.clone()),
             tenant_parser: tparser,
+            tenant_state: Arc::new(RwLock::new(PtyState::default())),
             tenant_tx: ttx,
             tenant_rx: Some(trx),
             tenant_status_tx: tpty_status_tx,
             tenant_status_rx: tpty_status_rx,
             tenant_resize_tx: None,
+            tenant_killer: None,
+            gate: self.gate.clone(),
+            config: self.config.clone(),
         }
     }
 
     pub async fn resize_screen(&mut self, rows: u16, cols: u16) {
+        // Only the area inside the overlay border belongs to the tenant
+        let rows = rows.saturating_sub(OVERLAY_INSET);
+        let cols = cols.saturating_sub(OVERLAY_INSET);
+
         // Update the parser size
-        self.tenant_parser.write().unwrap().set_size(rows, cols);
+        {
+            let mut parser = self.tenant_parser.write().unwrap();
+            parser.set_size(rows, cols);
+            self.tenant_state.write().unwrap().history = history_len(&mut parser);
+        }
 
         // Send resize command through channel if available
-        if let Some(resize_tx) = &self.tenant_resize_tx {
-            if let Err(e) = resize_tx.try_send((rows, cols)) {
-                eprintln!("Failed to send resize command: {}", e);
-            }
+        if let Some(resize_tx) = &self.tenant_resize_tx
+            && let Err(e) = resize_tx.try_send((rows, cols))
+        {
+            eprintln!("Failed to send resize command: {}", e);
         }
     }
 
+    pub fn restart(&mut self) {
+        // Killing the shell lets the usual exit path renew the lease
+        if let Some(mut killer) = self.tenant_killer.take()
+            && let Err(e) = killer.kill()
+        {
+            eprintln!("Failed to kill tenant: {}", e);
+        }
+        self.tenant.is_dead = true;
+    }
+
+    pub fn set_killer(&mut self, killer: Box<dyn ChildKiller + Send + Sync>) {
+        self.tenant_killer = Some(killer);
+    }
+
     pub fn set_resize_sender(&mut self, resize_tx: Sender<(u16, u16)>) {
         self.tenant_resize_tx = Some(resize_tx);
     }
@@ -1,12 +1,28 @@
+pub mod bench;
+pub mod copy;
 pub mod input;
 pub mod lease;
+pub mod scroll;
+pub mod tap;
 pub mod ui;
+use std::sync::Arc;
+
 use anyhow::Result;
 
+use crate::config::Config;
 use ui::owner::Container;
 
 pub async fn run() -> Result<()> {
-    let mut uncl = Container::new();
+    let config = Arc::new(Config::load()?);
+    if std::env::args()
+        .skip(1)
+        .any(|arg| arg == "--bench-throughput")
+    {
+        return tokio::task::spawn_blocking(move || bench::run(&config)).await?;
+    }
+
+    tap::host::probe();
+    let mut uncl = Container::new(config);
     uncl.initialize_pty().await.unwrap();
     Ok(())
 }
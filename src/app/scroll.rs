@@ -0,0 +1,168 @@
+use std::sync::RwLock;
+
+use vt100::Parser;
+
+use crate::app::tap::PtyState;
+
+// Rows moved per mouse wheel notch
+pub const WHEEL_LINES: isize = 3;
+
+// Where a pane is looking: `offset` rows up into `len` rows of history
+#[derive(Clone, Copy)]
+pub struct View {
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl View {
+    // History line shown on the first row of the pane. Lines are numbered
+    // from the oldest history row, so the live screen starts at `len`.
+    pub fn top(&self) -> usize {
+        self.len.saturating_sub(self.offset)
+    }
+
+    // What the pane's title says, e.g. "[scroll 12/340]" or "[copy]"
+    pub fn status(&self, mode: Option<&str>) -> Option<String> {
+        let mode = match mode {
+            Some(mode) => mode,
+            None if self.offset > 0 => "scroll",
+            None => return None,
+        };
+
+        if self.offset > 0 {
+            Some(format!("[{} {}/{}]", mode, self.offset, self.len))
+        } else {
+            Some(format!("[{}]", mode))
+        }
+    }
+}
+
+// Moves the view `delta` rows into the history, negative goes back towards
+// the live screen. vt100 keeps the view pinned while new output arrives.
+pub fn scroll_by(parser: &RwLock<Parser>, delta: isize) {
+    let mut parser = parser.write().unwrap();
+    let len = history_len(&mut parser);
+    let offset = parser.screen().scrollback().saturating_add_signed(delta);
+    parser.set_scrollback(offset.min(len));
+}
+
+pub fn scroll_page(parser: &RwLock<Parser>, up: bool) {
+    let rows = parser.read().unwrap().screen().size().0.max(1) as isize;
+    scroll_by(parser, if up { rows } else { -rows });
+}
+
+pub fn to_live(parser: &RwLock<Parser>) {
+    if parser.read().unwrap().screen().scrollback() != 0 {
+        parser.write().unwrap().set_scrollback(0);
+    }
+}
+
+// Takes the guards the frame is drawn under, so the view matches the
+// screen that's drawn and the history the tap measured for it
+pub fn view(parser: &Parser, state: &PtyState) -> View {
+    View {
+        offset: parser.screen().scrollback(),
+        len: state.history,
+    }
+}
+
+// Rows of history in reach. vt100 0.15 works out `rows - offset` for every
+// row it hands out, which goes below zero once the view is more than a
+// screen up, so uncl looks no further and pulls back a view that new output
+// or a resize pushed past it. vt100 only tells the history length by
+// clamping, so peek at the top and put the view back.
+pub fn history_len(parser: &mut Parser) -> usize {
+    let offset = parser.screen().scrollback();
+    parser.set_scrollback(usize::MAX);
+    let len = parser
+        .screen()
+        .scrollback()
+        .min(parser.screen().size().0 as usize);
+    parser.set_scrollback(offset.min(len));
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser(lines: usize) -> RwLock<Parser> {
+        let mut parser = Parser::new(5, 10, 100);
+        for i in 0..lines {
+            parser.process(format!("{}\r\n", i).as_bytes());
+        }
+        RwLock::new(parser)
+    }
+
+    fn state(parser: &RwLock<Parser>) -> PtyState {
+        PtyState {
+            history: history_len(&mut parser.write().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn measures_history_in_place() {
+        let parser = parser(20);
+        scroll_by(&parser, 3);
+
+        let mut parser = parser.write().unwrap();
+        assert_eq!(history_len(&mut parser), 5);
+        assert_eq!(parser.screen().scrollback(), 3);
+    }
+
+    #[test]
+    fn views_without_moving() {
+        let parser = parser(20);
+        let state = state(&parser);
+        let view = || view(&parser.read().unwrap(), &state);
+
+        let live = view();
+        assert_eq!((live.offset, live.len, live.top()), (0, 5, 5));
+        assert_eq!(live.status(None), None);
+
+        scroll_by(&parser, 100);
+        let top = view();
+        assert_eq!((top.offset, top.top()), (5, 0));
+        assert_eq!(top.status(None).as_deref(), Some("[scroll 5/5]"));
+        assert_eq!(parser.read().unwrap().screen().scrollback(), 5);
+
+        scroll_page(&parser, false);
+        assert_eq!(view().offset, 0);
+        to_live(&parser);
+        assert_eq!(view().offset, 0);
+    }
+
+    #[test]
+    fn keeps_the_view_within_a_screen() {
+        let parser = parser(20);
+        scroll_by(&parser, 5);
+
+        // vt100 pins the view by moving it further up for every new line
+        parser.write().unwrap().process(b"a\r\nb\r\n");
+        assert_eq!(history_len(&mut parser.write().unwrap()), 5);
+        let parser = parser.read().unwrap();
+        assert_eq!(parser.screen().scrollback(), 5);
+        let rows: Vec<_> = parser.screen().rows(0, 10).collect();
+        assert_eq!(rows, ["13", "14", "15", "16", "17"]);
+    }
+
+    #[test]
+    fn pulls_the_view_back_on_a_shrink() {
+        let parser = parser(20);
+        scroll_by(&parser, 5);
+
+        let mut parser = parser.write().unwrap();
+        parser.set_size(3, 10);
+        assert_eq!(history_len(&mut parser), 3);
+        assert_eq!(parser.screen().scrollback(), 3);
+        let rows: Vec<_> = parser.screen().rows(0, 10).collect();
+        assert_eq!(rows, ["13", "14", "15"]);
+    }
+
+    #[test]
+    fn tops_out_at_the_first_line() {
+        let view = View { offset: 7, len: 5 };
+        assert_eq!(view.top(), 0);
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    sync::{Condvar, Mutex},
+    time::Duration,
+};
+
+use tokio::sync::{Notify, futures::Notified};
+
+// Bytes run through vt100 under one lock, so a flood never keeps a screen
+// from the UI for long
+pub const SLICE: usize = 16 * 1024;
+
+// Longest a PTY reader steps aside for the UI before it carries on anyway
+const MAX_WAIT: Duration = Duration::from_millis(50);
+
+// Paces the PTY readers against the UI. Readers say when a screen changed
+// and wait between slices while the UI holds the gate, so keys and frames
+// get the locks right away however fast the output comes. A reader that
+// waits stops reading, which in turn holds up the app writing.
+#[derive(Default)]
+pub struct Gate {
+    output: Notify,
+    held: Mutex<bool>,
+    free: Condvar,
+}
+
+pub struct Hold<'a>(&'a Gate);
+
+impl Gate {
+    pub fn changed(&self) {
+        self.output.notify_one();
+    }
+
+    pub fn notified(&self) -> Notified<'_> {
+        self.output.notified()
+    }
+
+    // Keeps the readers out until the hold is dropped
+    pub fn hold(&self) -> Hold<'_> {
+        *self.held.lock().unwrap() = true;
+        Hold(self)
+    }
+
+    pub fn wait(&self) {
+        let held = self.held.lock().unwrap();
+        let _ = self.free.wait_timeout_while(held, MAX_WAIT, |held| *held);
+    }
+}
+
+impl Drop for Hold<'_> {
+    fn drop(&mut self) {
+        *self.0.held.lock().unwrap() = false;
+        self.0.free.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{sync::mpsc, thread, time::Instant};
+
+    #[test]
+    fn waits_out_a_hold_until_it_drops() {
+        let gate = Gate::default();
+        let (done, finished) = mpsc::channel();
+        thread::scope(|scope| {
+            let hold = gate.hold();
+            let start = Instant::now();
+            scope.spawn(|| {
+                gate.wait();
+                done.send(()).unwrap();
+            });
+
+            thread::sleep(MAX_WAIT / 5);
+            assert!(finished.try_recv().is_err());
+            drop(hold);
+            finished.recv_timeout(MAX_WAIT).unwrap();
+            assert!(start.elapsed() < MAX_WAIT);
+        });
+    }
+
+    #[test]
+    fn gives_up_on_a_hold_after_a_while() {
+        let gate = Gate::default();
+        let _hold = gate.hold();
+        let start = Instant::now();
+        gate.wait();
+        assert!(start.elapsed() >= MAX_WAIT);
+    }
+
+    #[test]
+    fn passes_a_free_gate() {
+        let gate = Gate::default();
+        drop(gate.hold());
+        let start = Instant::now();
+        gate.wait();
+        assert!(start.elapsed() < MAX_WAIT);
+    }
+
+    #[tokio::test]
+    async fn tells_the_ui_about_changes() {
+        let gate = Gate::default();
+        // A change before anyone listens isn't lost
+        gate.changed();
+        tokio::time::timeout(MAX_WAIT, gate.notified())
+            .await
+            .unwrap();
+    }
+}
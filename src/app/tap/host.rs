@@ -0,0 +1,225 @@
+use std::{
+    io::{self, IsTerminal},
+    sync::{Mutex, OnceLock},
+};
+
+#[cfg(unix)]
+use std::{
+    io::Write,
+    os::fd::AsRawFd,
+    time::{Duration, Instant},
+};
+
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+
+use super::scanner::{Scanner, Sequence, parse_params};
+
+// How long a quiet host gets to answer before uncl starts without it
+#[cfg(unix)]
+const PROBE_TIMEOUT: Duration = Duration::from_millis(200);
+
+// The host's default colors as it reported them, e.g. "rgb:ffff/ffff/ffff"
+#[derive(Default)]
+pub struct HostColors {
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+}
+
+static COLORS: OnceLock<HostColors> = OnceLock::new();
+
+// Keys typed while the probe waited, owed to the owner shell
+static TYPED: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+pub fn colors() -> &'static HostColors {
+    COLORS.get_or_init(HostColors::default)
+}
+
+pub fn take_typed() -> Vec<u8> {
+    std::mem::take(&mut TYPED.lock().unwrap())
+}
+
+// Asks the host for its colors once before anything else reads the
+// terminal, so OSC 10/11 queries from inside are answered without a round
+// trip. DA1 goes last: every terminal answers it, so it marks the end.
+pub fn probe() {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() || !io::stdout().is_terminal() {
+        return;
+    }
+
+    let mut replies = Replies::new();
+    if let Err(e) = enable_raw_mode().and_then(|_| query(&stdin, &mut replies)) {
+        eprintln!("Failed to query host colors: {}", e);
+    }
+    let _ = disable_raw_mode();
+    let _ = COLORS.set(replies.colors);
+    TYPED.lock().unwrap().append(&mut replies.typed);
+}
+
+#[cfg(unix)]
+fn query(stdin: &io::Stdin, replies: &mut Replies) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.write_all(b"\x1b]10;?\x1b\\\x1b]11;?\x1b\\\x1b[c")?;
+    stdout.flush()?;
+
+    let fd = stdin.as_raw_fd();
+    let mut buf = [0u8; 1024];
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        let mut poll = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: one valid pollfd, and `buf` outlives the read into it
+        let size = unsafe {
+            if libc::poll(&mut poll, 1, left.as_millis() as i32) <= 0 {
+                break;
+            }
+            libc::read(fd, buf.as_mut_ptr().cast(), buf.len())
+        };
+        if size <= 0 {
+            break;
+        }
+
+        for &byte in &buf[..size as usize] {
+            replies.advance(byte);
+        }
+        if replies.done {
+            break;
+        }
+    }
+
+    replies.finish();
+    Ok(())
+}
+
+// Without a way to wait on stdin with a timeout the probe is skipped, and
+// queries from inside go unanswered
+#[cfg(not(unix))]
+fn query(_stdin: &io::Stdin, _replies: &mut Replies) -> io::Result<()> {
+    Ok(())
+}
+
+// Splits what the host sends during the probe into its answers and
+// whatever the user typed meanwhile
+#[cfg_attr(not(unix), allow(dead_code))]
+struct Replies {
+    colors: HostColors,
+    typed: Vec<u8>,
+    // The sequence being read, kept back until it turns out not to be an
+    // answer
+    held: Vec<u8>,
+    scanner: Scanner,
+    // Set by the DA1 answer, anything after it was typed
+    done: bool,
+}
+
+#[cfg_attr(not(unix), allow(dead_code))]
+impl Replies {
+    fn new() -> Self {
+        Self {
+            colors: HostColors::default(),
+            typed: Vec::new(),
+            held: Vec::new(),
+            scanner: Scanner::new(),
+            done: false,
+        }
+    }
+
+    fn advance(&mut self, byte: u8) {
+        if self.done {
+            self.typed.push(byte);
+            return;
+        }
+
+        // An ESC outside a string ends whatever came before it
+        if byte == 0x1b && !self.scanner.in_string() {
+            self.typed.append(&mut self.held);
+        }
+        self.held.push(byte);
+
+        let reply = match self.scanner.advance(byte) {
+            Some(Sequence::Osc(payload)) => {
+                match String::from_utf8_lossy(payload).split_once(';') {
+                    Some(("10", color)) => {
+                        self.colors.foreground = Some(color.to_string());
+                        true
+                    }
+                    Some(("11", color)) => {
+                        self.colors.background = Some(color.to_string());
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Some(Sequence::Csi {
+                params,
+                intermediates: [],
+                action: b'c',
+            }) if parse_params(params).0 == Some(b'?') => {
+                self.done = true;
+                true
+            }
+            _ => false,
+        };
+
+        if reply {
+            self.held.clear();
+        } else if !self.scanner.in_sequence() {
+            self.typed.append(&mut self.held);
+        }
+    }
+
+    // A sequence cut off by the timeout was typed too
+    fn finish(&mut self) {
+        self.typed.append(&mut self.held);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(input: &[u8]) -> Replies {
+        let mut replies = Replies::new();
+        for &byte in input {
+            replies.advance(byte);
+        }
+        replies.finish();
+        replies
+    }
+
+    #[test]
+    fn reads_the_answers() {
+        let replies =
+            split(b"\x1b]10;rgb:ffff/ffff/ffff\x1b\\\x1b]11;rgb:0000/0000/0000\x07\x1b[?62;22c");
+        assert_eq!(
+            replies.colors.foreground.as_deref(),
+            Some("rgb:ffff/ffff/ffff")
+        );
+        assert_eq!(
+            replies.colors.background.as_deref(),
+            Some("rgb:0000/0000/0000")
+        );
+        assert!(replies.done);
+        assert!(replies.typed.is_empty());
+    }
+
+    #[test]
+    fn keeps_what_was_typed() {
+        let replies = split(b"ls\x1b]10;rgb:1/2/3\x1b\\\x1b[A\x07\x1b\x1b[?1cx\r");
+        assert_eq!(replies.colors.foreground.as_deref(), Some("rgb:1/2/3"));
+        assert!(replies.done);
+        // The arrow key, Ctrl+G, a lone Esc and everything after DA1
+        assert_eq!(replies.typed, b"ls\x1b[A\x07\x1bx\r");
+    }
+
+    #[test]
+    fn keeps_a_sequence_the_timeout_cut_off() {
+        let replies = split(b"\x1b]11;rgb:0/0/0\x07\x1b[1;5");
+        assert!(!replies.done);
+        assert_eq!(replies.typed, b"\x1b[1;5");
+    }
+}
@@ -0,0 +1,530 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use vt100::Parser;
+
+use super::scanner::parse_params;
+
+// Images one PTY keeps at once, the oldest are dropped first
+const MAX_IMAGES: usize = 64;
+const MAX_BYTES: usize = 64 * 1024 * 1024;
+
+// Cell size to go by when the host doesn't report its pixel size
+const FALLBACK_CELL: (u32, u32) = (10, 20);
+
+// Markers come out of plane 15, which is all private use
+const MARKER_BASE: u32 = 0xF0000;
+const MARKER_COUNT: u32 = 0xFFFE;
+
+// Ids are shared by every PTY, they name the images on the host too
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone)]
+pub enum Graphic {
+    // The whole DCS sequence, the host gets it as it came
+    Sixel(Arc<[u8]>),
+    // Format keys of a kitty transmission, e.g. "f=100", and its base64 data
+    Kitty { keys: Arc<str>, data: Arc<str> },
+}
+
+#[derive(Clone)]
+pub struct Image {
+    pub id: u32,
+    pub graphic: Graphic,
+    pub cols: u16,
+    pub rows: u16,
+    // Size in pixels, when it's known
+    pub pixels: Option<(u32, u32)>,
+    // The id a kitty app gave it, for deleting it again
+    app_id: Option<u32>,
+}
+
+impl PartialEq for Image {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+// A kitty transmission as the app sent it, the keys of its first chunk and
+// all the data
+struct Transfer {
+    keys: Vec<(String, String)>,
+    data: String,
+}
+
+struct Stored {
+    id: u32,
+    keys: Vec<(String, String)>,
+    data: Arc<str>,
+}
+
+// vt100 drops sixel and kitty graphics, so the tap keeps them here and
+// fills the cells an image covers with a marker character of its own. The
+// markers move with the text, scroll into history and get written over,
+// and the container finds the images by them when it draws.
+#[derive(Default)]
+pub struct Images {
+    images: VecDeque<Image>,
+    bytes: usize,
+    // Kitty transmissions by the app's id, for placing them with `a=p`
+    stored: VecDeque<Stored>,
+    // A chunked kitty transmission that's still coming in
+    chunk: Option<Transfer>,
+    // Kitty images gone from here that the host can forget too
+    pub evicted: Vec<u32>,
+}
+
+pub fn marker(id: u32) -> char {
+    char::from_u32(MARKER_BASE + id % MARKER_COUNT).unwrap_or(' ')
+}
+
+pub fn marker_id(symbol: &str) -> Option<u32> {
+    let c = symbol.chars().next()? as u32;
+    (MARKER_BASE..MARKER_BASE + MARKER_COUNT)
+        .contains(&c)
+        .then(|| c - MARKER_BASE)
+}
+
+impl Images {
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Image> {
+        self.images.iter().find(|image| image.id == id)
+    }
+
+    // A DCS sequence is a sixel image when its final byte is `q`
+    pub fn sixel(&mut self, parser: &mut Parser, payload: &[u8]) {
+        let Some(start) = payload
+            .iter()
+            .position(|b| !b.is_ascii_digit() && *b != b';')
+            .filter(|&start| payload[start] == b'q')
+        else {
+            return;
+        };
+
+        let pixels = sixel_size(&payload[start + 1..]);
+        let (cols, rows) = cells(pixels);
+        let mut sequence = b"\x1bP".to_vec();
+        sequence.extend_from_slice(payload);
+        sequence.extend_from_slice(b"\x1b\\");
+        let image = Image {
+            id: next_id(),
+            graphic: Graphic::Sixel(sequence.into()),
+            cols,
+            rows,
+            pixels: Some(pixels),
+            app_id: None,
+        };
+        self.add(parser, image, false);
+    }
+
+    // An APC sequence starting with `G` is a kitty graphics command
+    pub fn kitty(&mut self, parser: &mut Parser, payload: &[u8], reply: &mut Vec<u8>) {
+        let Some(command) = payload.strip_prefix(b"G") else {
+            return;
+        };
+        let command = String::from_utf8_lossy(command);
+        let (control, data) = command.split_once(';').unwrap_or((&command, ""));
+        let keys: Vec<(String, String)> = control
+            .split(',')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let more = key(&keys, "m") == Some("1");
+
+        // Later chunks only carry `m`, the first one has the rest of the keys
+        let mut transfer = match self.chunk.take() {
+            Some(mut transfer) => {
+                transfer.data.push_str(data);
+                transfer
+            }
+            None => Transfer {
+                keys,
+                data: data.to_string(),
+            },
+        };
+        if more {
+            if transfer.data.len() < MAX_BYTES {
+                self.chunk = Some(transfer);
+            }
+            return;
+        }
+
+        let id = key(&transfer.keys, "i").and_then(|i| i.parse::<u32>().ok());
+        let quiet = key(&transfer.keys, "q").unwrap_or("0").to_string();
+        // Files and shared memory may not be on the host's machine
+        let direct = key(&transfer.keys, "t").is_none_or(|t| t == "d");
+        let answer = match key(&transfer.keys, "a").unwrap_or("t") {
+            "q" if direct => Ok(()),
+            "q" => Err("EINVAL:only direct transmission is supported"),
+            "t" | "T" if !direct => Err("EINVAL:only direct transmission is supported"),
+            "t" | "T" => {
+                let data: Arc<str> = std::mem::take(&mut transfer.data).into();
+                if key(&transfer.keys, "a") == Some("T") {
+                    self.place(parser, &transfer.keys, &transfer.keys, &data, id);
+                }
+                if let Some(id) = id {
+                    self.store(id, transfer.keys.clone(), data);
+                }
+                Ok(())
+            }
+            "p" => match self.stored.iter().find(|stored| Some(stored.id) == id) {
+                Some(stored) => {
+                    let (keys, data) = (stored.keys.clone(), stored.data.clone());
+                    self.place(parser, &keys, &transfer.keys, &data, id);
+                    Ok(())
+                }
+                None => Err("ENOENT:no such image"),
+            },
+            "d" => {
+                self.delete(&transfer.keys, id);
+                return;
+            }
+            _ => return,
+        };
+
+        let Some(id) = id else {
+            return;
+        };
+        match answer {
+            Ok(()) if quiet == "0" => {
+                reply.extend_from_slice(format!("\x1b_Gi={};OK\x1b\\", id).as_bytes())
+            }
+            Err(error) if quiet != "2" => {
+                reply.extend_from_slice(format!("\x1b_Gi={};{}\x1b\\", id, error).as_bytes())
+            }
+            _ => {}
+        }
+    }
+
+    fn store(&mut self, id: u32, keys: Vec<(String, String)>, data: Arc<str>) {
+        self.stored.retain(|stored| stored.id != id);
+        if self.stored.len() >= MAX_IMAGES {
+            self.stored.pop_front();
+        }
+        self.stored.push_back(Stored { id, keys, data });
+    }
+
+    // Puts a kitty image at the cursor, sized by the placement's `c` and
+    // `r` or else by its pixels
+    fn place(
+        &mut self,
+        parser: &mut Parser,
+        format: &[(String, String)],
+        placement: &[(String, String)],
+        data: &Arc<str>,
+        app_id: Option<u32>,
+    ) {
+        let number = |name| key(format, name).and_then(|v| v.parse::<u32>().ok());
+        let pixels = match (number("s"), number("v")) {
+            (Some(w), Some(h)) if w > 0 && h > 0 => Some((w, h)),
+            _ if key(format, "f") == Some("100") => png_size(data),
+            _ => None,
+        };
+
+        let fitted = pixels.map(cells);
+        let size = |name, fitted: Option<u16>| {
+            key(placement, name)
+                .and_then(|v| v.parse::<u16>().ok())
+                .filter(|&n| n > 0)
+                .or(fitted)
+        };
+        let (Some(cols), Some(rows)) = (
+            size("c", fitted.map(|f| f.0)),
+            size("r", fitted.map(|f| f.1)),
+        ) else {
+            return;
+        };
+
+        let keys: Vec<String> = format
+            .iter()
+            .filter(|(k, _)| matches!(k.as_str(), "f" | "s" | "v" | "o" | "S" | "O"))
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        let image = Image {
+            id: next_id(),
+            graphic: Graphic::Kitty {
+                keys: keys.join(",").into(),
+                data: data.clone(),
+            },
+            cols,
+            rows,
+            pixels,
+            app_id,
+        };
+        let stay = key(placement, "C") == Some("1");
+        self.add(parser, image, stay);
+    }
+
+    // `d=a` and `d=i` are the ones apps use, both forget the image here
+    fn delete(&mut self, keys: &[(String, String)], id: Option<u32>) {
+        let forget = |image: &Image| match key(keys, "d").unwrap_or("a") {
+            "a" | "A" => true,
+            "i" | "I" => id.is_some() && image.app_id == id,
+            _ => false,
+        };
+        let (gone, kept) = std::mem::take(&mut self.images)
+            .into_iter()
+            .partition(|image| matches!(image.graphic, Graphic::Kitty { .. }) && forget(image));
+        self.images = kept;
+        for image in gone {
+            self.drop_image(image);
+        }
+        match key(keys, "d") {
+            Some("A") => self.stored.clear(),
+            Some("I") => self.stored.retain(|stored| Some(stored.id) != id),
+            _ => {}
+        }
+    }
+
+    // Marks the cells under the image and leaves the cursor where the host
+    // would: after a kitty image, at the start of a sixel's last row. Rows
+    // past a screen's worth would only scroll away again, so they aren't
+    // marked.
+    fn add(&mut self, parser: &mut Parser, image: Image, stay: bool) {
+        let screen = parser.screen();
+        let (height, width) = screen.size();
+        let col = screen.cursor_position().1;
+        let cols = image.cols.min(width.saturating_sub(col));
+        let rows = image.rows.min(height);
+        if cols == 0 || rows == 0 {
+            return;
+        }
+
+        let line: String = std::iter::repeat_n(marker(image.id), cols as usize).collect();
+        let mut text = String::new();
+        for row in 0..rows {
+            if row > 0 {
+                text.push_str(&format!("\n\x1b[{}G", col + 1));
+            }
+            text.push_str(&line);
+        }
+        if stay && rows > 1 {
+            text.push_str(&format!("\x1b[{}A", rows - 1));
+        }
+        if stay || matches!(image.graphic, Graphic::Sixel(_)) {
+            text.push_str(&format!("\x1b[{}G", col + 1));
+        }
+        parser.process(text.as_bytes());
+
+        self.bytes += size(&image);
+        self.images.push_back(image);
+        while self.images.len() > MAX_IMAGES || self.bytes > MAX_BYTES {
+            let Some(image) = self.images.pop_front() else {
+                break;
+            };
+            self.drop_image(image);
+        }
+    }
+
+    fn drop_image(&mut self, image: Image) {
+        self.bytes -= size(&image);
+        if matches!(image.graphic, Graphic::Kitty { .. }) {
+            self.evicted.push(image.id);
+        }
+    }
+
+    // RIS forgets every image
+    pub fn clear(&mut self) {
+        for image in std::mem::take(&mut self.images) {
+            self.drop_image(image);
+        }
+        self.stored.clear();
+        self.chunk = None;
+    }
+}
+
+fn next_id() -> u32 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed) % MARKER_COUNT
+}
+
+fn key<'a>(keys: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    keys.iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+fn size(image: &Image) -> usize {
+    match &image.graphic {
+        Graphic::Sixel(sequence) => sequence.len(),
+        Graphic::Kitty { data, .. } => data.len(),
+    }
+}
+
+// Cells an image of `pixels` covers, going by the host's cell size
+fn cells(pixels: (u32, u32)) -> (u16, u16) {
+    let cell = crossterm::terminal::window_size()
+        .ok()
+        .filter(|size| size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0)
+        .map_or(FALLBACK_CELL, |size| {
+            (
+                (size.width / size.columns).max(1) as u32,
+                (size.height / size.rows).max(1) as u32,
+            )
+        });
+    (
+        pixels.0.div_ceil(cell.0).min(u16::MAX as u32) as u16,
+        pixels.1.div_ceil(cell.1).min(u16::MAX as u32) as u16,
+    )
+}
+
+// Raster attributes give the size up front, otherwise it's counted off the
+// widest line and the number of six pixel bands
+fn sixel_size(data: &[u8]) -> (u32, u32) {
+    let (mut x, mut width, mut bands) = (0u32, 0u32, 1u32);
+    let mut i = 0;
+    while i < data.len() {
+        let byte = data[i];
+        i += 1;
+        match byte {
+            b'"' => {
+                if let [_, _, w, h, ..] = numbers(data, &mut i)[..]
+                    && w > 0
+                    && h > 0
+                {
+                    return (w, h);
+                }
+            }
+            b'!' => {
+                let count = numbers(data, &mut i).first().copied().unwrap_or(1);
+                if data.get(i).is_some_and(|b| (b'?'..=b'~').contains(b)) {
+                    i += 1;
+                    x = x.saturating_add(count.max(1));
+                }
+            }
+            b'#' => {
+                numbers(data, &mut i);
+            }
+            b'$' => {
+                width = width.max(x);
+                x = 0;
+            }
+            b'-' => {
+                width = width.max(x);
+                x = 0;
+                bands += 1;
+            }
+            b'?'..=b'~' => x = x.saturating_add(1),
+            _ => {}
+        }
+    }
+
+    // A trailing `-` starts a band that's never drawn
+    if data.ends_with(b"-") {
+        bands -= 1;
+    }
+    (width.max(x), bands.saturating_mul(6))
+}
+
+fn numbers(data: &[u8], i: &mut usize) -> Vec<u32> {
+    let start = *i;
+    while data
+        .get(*i)
+        .is_some_and(|b| b.is_ascii_digit() || *b == b';')
+    {
+        *i += 1;
+    }
+    parse_params(&data[start..*i]).1
+}
+
+// Width and height from the IHDR chunk, which comes first in every PNG
+fn png_size(data: &str) -> Option<(u32, u32)> {
+    let head = STANDARD.decode(data.get(..32)?).ok()?;
+    if !head.starts_with(b"\x89PNG") {
+        return None;
+    }
+    // Padding can end the base64 early and leave the head short
+    let width = u32::from_be_bytes(head.get(16..20)?.try_into().ok()?);
+    let height = u32::from_be_bytes(head.get(20..24)?.try_into().ok()?);
+    Some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::app::scroll::history_len;
+
+    fn png(width: u32, height: u32) -> String {
+        let mut head = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        head.extend_from_slice(&width.to_be_bytes());
+        head.extend_from_slice(&height.to_be_bytes());
+        head.extend_from_slice(b"rest of the file");
+        STANDARD.encode(head)
+    }
+
+    #[test]
+    fn sizes_sixel() {
+        let cases: &[(&[u8], (u32, u32))] = &[
+            // Raster attributes win over the pixels
+            (b"\"1;1;20;40#0~~", (20, 40)),
+            // Counted off the widest line and the bands
+            (b"#0~~~$~~-~", (3, 12)),
+            (b"!5~$~-", (5, 6)),
+            // Zero or missing raster sizes are counted instead
+            (b"\"1;1;0;40~~", (2, 6)),
+            (b"\"1;1~~", (2, 6)),
+            // Cut off anywhere
+            (b"", (0, 6)),
+            (b"\"1;1;2", (0, 6)),
+            (b"!12", (0, 6)),
+            (b"#0;2;100", (0, 6)),
+            // Huge repeats saturate
+            (b"!99999999999~!99999999999~", (u32::MAX, 6)),
+        ];
+        for (data, size) in cases {
+            assert_eq!(
+                sixel_size(data),
+                *size,
+                "{:?}",
+                String::from_utf8_lossy(data)
+            );
+        }
+    }
+
+    #[test]
+    fn sizes_png() {
+        assert_eq!(png_size(&png(640, 480)), Some((640, 480)));
+        // The head is all that's read
+        assert_eq!(png_size(&png(1, 2)[..32]), Some((1, 2)));
+
+        // Too short, or padded short of the size
+        assert_eq!(png_size(&png(1, 2)[..31]), None);
+        let padded = STANDARD.encode(&STANDARD.decode(png(1, 2)).unwrap()[..22]);
+        assert_eq!(padded.len(), 32);
+        assert_eq!(png_size(&padded), None);
+        // Not a PNG, not base64, or cut inside a character
+        assert_eq!(png_size(&STANDARD.encode([0u8; 24])), None);
+        assert_eq!(png_size(&"!".repeat(40)), None);
+        assert_eq!(png_size(&format!("{}é", &png(1, 2)[..31])), None);
+    }
+
+    #[test]
+    fn marks_a_screen_at_most() {
+        let mut parser = Parser::new(5, 10, 100);
+        let mut images = Images::default();
+        let mut reply = Vec::new();
+        let payload = format!("Ga=T,f=100,i=1,q=2,r=65535,c=65535;{}", png(8, 8));
+        images.kitty(&mut parser, payload.as_bytes(), &mut reply);
+
+        let image = images.images.back().expect("image is kept");
+        assert_eq!((image.cols, image.rows), (65535, 65535));
+        let id = image.id;
+        let screen = parser.screen();
+        let marked = (0..5)
+            .flat_map(|row| (0..10).map(move |col| (row, col)))
+            .filter(|&(row, col)| marker_id(&screen.cell(row, col).unwrap().contents()) == Some(id))
+            .count();
+        assert_eq!(marked, 5 * 10);
+        // Nothing went up into the history
+        assert_eq!(history_len(&mut parser), 0);
+    }
+}
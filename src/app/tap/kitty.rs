@@ -0,0 +1,156 @@
+// Progressive keyboard enhancement flags from the kitty keyboard protocol
+pub const DISAMBIGUATE: u8 = 0b1;
+pub const REPORT_EVENTS: u8 = 0b10;
+pub const REPORT_ALTERNATES: u8 = 0b100;
+pub const REPORT_ALL_KEYS: u8 = 0b1000;
+pub const REPORT_TEXT: u8 = 0b10000;
+
+const ALL_FLAGS: u8 = 0b11111;
+
+// Same depth limit kitty uses, the oldest entries fall off first
+const MAX_DEPTH: usize = 16;
+
+// Each screen keeps its own stack, so an app on the alternate screen can't
+// leave the shell on the main screen with its flags
+#[derive(Default)]
+pub struct KittyKeyboard {
+    main: Vec<u8>,
+    alternate: Vec<u8>,
+    on_alternate: bool,
+}
+
+impl KittyKeyboard {
+    pub fn flags(&self) -> u8 {
+        self.stack().last().copied().unwrap_or(0)
+    }
+
+    // `CSI > flags u`
+    pub fn push(&mut self, flags: u32) {
+        let stack = self.stack_mut();
+        if stack.len() >= MAX_DEPTH {
+            stack.remove(0);
+        }
+        stack.push(flags as u8 & ALL_FLAGS);
+    }
+
+    // `CSI < n u`
+    pub fn pop(&mut self, count: u32) {
+        let stack = self.stack_mut();
+        stack.truncate(stack.len().saturating_sub(count.max(1) as usize));
+    }
+
+    // `CSI = flags ; mode u`: 1 replaces, 2 adds and 3 removes flags
+    pub fn set(&mut self, flags: u32, mode: u32) {
+        let flags = flags as u8 & ALL_FLAGS;
+        let current = self.flags();
+        let updated = match mode {
+            2 => current | flags,
+            3 => current & !flags,
+            _ => flags,
+        };
+
+        let stack = self.stack_mut();
+        match stack.last_mut() {
+            Some(top) => *top = updated,
+            None => stack.push(updated),
+        }
+    }
+
+    pub fn set_alternate(&mut self, on: bool) {
+        if on && !self.on_alternate {
+            self.alternate.clear();
+        }
+        self.on_alternate = on;
+    }
+
+    fn stack(&self) -> &Vec<u8> {
+        if self.on_alternate {
+            &self.alternate
+        } else {
+            &self.main
+        }
+    }
+
+    fn stack_mut(&mut self) -> &mut Vec<u8> {
+        if self.on_alternate {
+            &mut self.alternate
+        } else {
+            &mut self.main
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pushes_and_pops() {
+        let mut kitty = KittyKeyboard::default();
+        kitty.push(0b1);
+        kitty.push(0b1011);
+        assert_eq!(kitty.flags(), 0b1011);
+        // Unknown flags are dropped
+        kitty.push(0b1100000 | REPORT_TEXT as u32);
+        assert_eq!(kitty.flags(), REPORT_TEXT);
+
+        kitty.pop(1);
+        assert_eq!(kitty.flags(), 0b1011);
+        // Popping nothing pops one, and popping too many empties the stack
+        kitty.pop(0);
+        assert_eq!(kitty.flags(), 0b1);
+        kitty.pop(5);
+        assert_eq!(kitty.flags(), 0);
+    }
+
+    #[test]
+    fn sets_by_mode() {
+        let mut kitty = KittyKeyboard::default();
+        // Setting on an empty stack starts it
+        kitty.set(0b101, 1);
+        assert_eq!(kitty.flags(), 0b101);
+        kitty.set(0b10, 2);
+        assert_eq!(kitty.flags(), 0b111);
+        kitty.set(0b11, 3);
+        assert_eq!(kitty.flags(), 0b100);
+        // Anything else replaces, on the top entry only
+        kitty.push(0b1);
+        kitty.set(0b1000, 9);
+        assert_eq!(kitty.flags(), 0b1000);
+        kitty.pop(1);
+        assert_eq!(kitty.flags(), 0b100);
+    }
+
+    #[test]
+    fn drops_the_oldest_past_the_limit() {
+        let mut kitty = KittyKeyboard::default();
+        for flags in 0..MAX_DEPTH as u32 + 2 {
+            kitty.push(flags);
+        }
+        assert_eq!(kitty.main.len(), MAX_DEPTH);
+        assert_eq!(kitty.main[0], 2);
+        assert_eq!(kitty.flags(), MAX_DEPTH as u8 + 1);
+    }
+
+    #[test]
+    fn keeps_a_stack_per_screen() {
+        let mut kitty = KittyKeyboard::default();
+        kitty.push(0b1);
+
+        kitty.set_alternate(true);
+        assert_eq!(kitty.flags(), 0);
+        kitty.push(0b11);
+        kitty.push(0b111);
+        assert_eq!(kitty.flags(), 0b111);
+        // Switching again while there changes nothing
+        kitty.set_alternate(true);
+        assert_eq!(kitty.flags(), 0b111);
+
+        // The main screen's flags come back untouched
+        kitty.set_alternate(false);
+        assert_eq!(kitty.flags(), 0b1);
+        // and the alternate screen starts over
+        kitty.set_alternate(true);
+        assert_eq!(kitty.flags(), 0);
+    }
+}
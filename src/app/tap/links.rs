@@ -0,0 +1,249 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use unicode_width::UnicodeWidthChar;
+use vt100::{Cell, Screen};
+
+// Links remembered at once, the oldest are forgotten first
+const MAX_LINKS: usize = 4096;
+
+// Tags come out of the variation selectors VS17 to VS256, which have no
+// width, so vt100 keeps them in the cell of the character before them. A
+// cell holds six codepoints, two tag digits leave room for combining marks
+// and still count far past MAX_LINKS.
+const TAG_BASE: u32 = 0xE0100;
+const TAG_COUNT: u32 = 240;
+const TAG_LEN: usize = 2;
+const TAG_IDS: u32 = TAG_COUNT * TAG_COUNT;
+
+// vt100 has nowhere to keep hyperlinks, so the tap writes a tag naming the
+// link after every character printed under one. Like the image markers the
+// tags live in the cells, moving with the text through scrolling and
+// history and going away with whatever writes over them. The container
+// strips them again before anything is drawn or copied.
+#[derive(Default)]
+pub struct Links {
+    // The link OSC 8 opened and its tag, if it hasn't been closed yet
+    open: Option<(u32, String)>,
+    targets: HashMap<u32, Arc<str>>,
+    order: VecDeque<u32>,
+    next: u32,
+    // Continuation bytes still to come for the character being printed,
+    // and its bits so far
+    utf8: u8,
+    code: u32,
+}
+
+impl Links {
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open.is_some()
+    }
+
+    // OSC 8 with an empty target closes the link
+    pub fn set(&mut self, payload: &[u8]) {
+        let link = String::from_utf8_lossy(payload);
+        self.utf8 = 0;
+        self.open = match link.split_once(';') {
+            Some((_, "")) | None => None,
+            Some(_) => {
+                let id = self.add(Arc::from(link.as_ref()));
+                Some((id, tag(id)))
+            }
+        };
+    }
+
+    // Reopening the last link again carries on with its id, so text written
+    // in a few pieces stays one link
+    fn add(&mut self, link: Arc<str>) -> u32 {
+        if let Some(&last) = self.order.back()
+            && self.targets.get(&last) == Some(&link)
+        {
+            return last;
+        }
+
+        if self.order.len() >= MAX_LINKS
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.targets.remove(&oldest);
+        }
+        let id = self.next;
+        self.next = (self.next + 1) % TAG_IDS;
+        self.targets.insert(id, link);
+        self.order.push_back(id);
+        id
+    }
+
+    // Fed every byte of plain text while a link is open, gives the tag to
+    // write after the ones that finish a printable character
+    pub fn tag_after(&mut self, byte: u8) -> Option<&[u8]> {
+        let ends = match byte {
+            0x20..=0x7e => Some(byte as u32),
+            0x80..=0xbf if self.utf8 > 0 => {
+                self.utf8 -= 1;
+                self.code = self.code << 6 | (byte & 0x3f) as u32;
+                (self.utf8 == 0).then_some(self.code)
+            }
+            0xc0..=0xdf => self.lead(1, byte & 0x1f),
+            0xe0..=0xef => self.lead(2, byte & 0x0f),
+            0xf0..=0xf7 => self.lead(3, byte & 0x07),
+            _ => {
+                self.utf8 = 0;
+                None
+            }
+        };
+        let printed = ends.and_then(char::from_u32).is_some_and(takes_a_cell);
+        let (_, tag) = self.open.as_ref().filter(|_| printed)?;
+        Some(tag.as_bytes())
+    }
+
+    fn lead(&mut self, continuations: u8, bits: u8) -> Option<u32> {
+        self.utf8 = continuations;
+        self.code = bits as u32;
+        None
+    }
+
+    // The link a cell was written under, as sent, e.g. "id=1;file:///tmp"
+    pub fn get(&self, cell: &Cell) -> Option<&Arc<str>> {
+        self.targets.get(&id(&cell.contents())?)
+    }
+
+    pub fn at(&self, screen: &Screen, row: u16, col: u16) -> Option<&Arc<str>> {
+        let cell = screen.cell(row, col)?;
+        if cell.is_wide_continuation() && col > 0 {
+            return self.at(screen, row, col - 1);
+        }
+        self.get(cell)
+    }
+}
+
+// Where a link points, without its parameters
+pub fn target(link: &str) -> &str {
+    link.split_once(';').map_or("", |(_, target)| target)
+}
+
+// A cell's text with any tag taken out
+pub fn untag(contents: &str) -> String {
+    contents.chars().filter(|&c| digit(c).is_none()).collect()
+}
+
+pub fn is_tagged(contents: &str) -> bool {
+    contents.chars().any(|c| digit(c).is_some())
+}
+
+fn tag(id: u32) -> String {
+    let mut digits = [0; TAG_LEN];
+    let mut n = id;
+    for digit in digits.iter_mut().rev() {
+        *digit = n % TAG_COUNT;
+        n /= TAG_COUNT;
+    }
+    digits
+        .iter()
+        .filter_map(|&d| char::from_u32(TAG_BASE + d))
+        .collect()
+}
+
+fn id(contents: &str) -> Option<u32> {
+    let mut digits = contents.chars().filter_map(digit);
+    let mut id = 0;
+    for _ in 0..TAG_LEN {
+        id = id * TAG_COUNT + digits.next()?;
+    }
+    Some(id)
+}
+
+// Marks of no width join the cell before them, which already has its tag,
+// and vt100 draws no C1 controls at all
+fn takes_a_cell(c: char) -> bool {
+    c.width().map_or(c as u32 >= 0x100, |width| width > 0)
+}
+
+fn digit(c: char) -> Option<u32> {
+    (c as u32)
+        .checked_sub(TAG_BASE)
+        .filter(|&digit| digit < TAG_COUNT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_round_trip() {
+        for n in [0, 1, TAG_COUNT - 1, TAG_COUNT, 12_345, TAG_IDS - 1] {
+            let contents = format!("x{}", tag(n));
+            assert_eq!(id(&contents), Some(n));
+            assert!(is_tagged(&contents));
+            assert_eq!(untag(&contents), "x");
+        }
+        assert_eq!(id("x"), None);
+        assert!(!is_tagged("e\u{301}"));
+    }
+
+    #[test]
+    fn tags_whole_characters() {
+        let mut links = Links::default();
+        assert_eq!(links.tag_after(b'a'), None);
+
+        links.set(b";http://x");
+        let tag = tag(0).into_bytes();
+        assert_eq!(links.tag_after(b'a'), Some(&tag[..]));
+        // Only the last byte of "é" and "中" ends the character, and a
+        // combining accent goes in the cell that's already tagged
+        let text: Vec<bool> = "é中\u{301}"
+            .bytes()
+            .map(|b| links.tag_after(b).is_some())
+            .collect();
+        assert_eq!(text, [false, true, false, false, true, false, false]);
+        // Controls and stray continuation bytes get nothing
+        assert_eq!(links.tag_after(b'\n'), None);
+        assert_eq!(links.tag_after(0x80), None);
+
+        links.set(b";");
+        assert_eq!(links.tag_after(b'a'), None);
+    }
+
+    #[test]
+    fn reuses_the_last_link() {
+        let mut links = Links::default();
+        links.set(b";http://x");
+        links.set(b";");
+        links.set(b";http://x");
+        links.set(b"id=2;http://y");
+        assert_eq!(links.order, [0, 1]);
+        assert_eq!(links.targets[&1].as_ref(), "id=2;http://y");
+        assert_eq!(target(&links.targets[&1]), "http://y");
+    }
+
+    #[test]
+    fn keeps_combining_marks_with_the_link() {
+        let mut parser = vt100::Parser::new(1, 10, 0);
+        let mut links = Links::default();
+        links.set(b";http://x");
+        // Three marks fill the cell up, vt100 drops the fourth
+        for byte in "e\u{301}\u{302}\u{303}\u{304}a".bytes() {
+            parser.process(&[byte]);
+            if let Some(tag) = links.tag_after(byte) {
+                parser.process(tag);
+            }
+        }
+
+        let screen = parser.screen();
+        let cell = screen.cell(0, 0).unwrap();
+        assert_eq!(untag(&cell.contents()), "e\u{301}\u{302}\u{303}");
+        assert_eq!(
+            links.at(screen, 0, 0).map(|link| link.as_ref()),
+            Some(";http://x")
+        );
+        assert_eq!(
+            links.at(screen, 0, 1).map(|link| link.as_ref()),
+            Some(";http://x")
+        );
+    }
+}
@@ -0,0 +1,501 @@
+pub mod gate;
+pub mod host;
+pub mod images;
+pub mod kitty;
+pub mod links;
+pub mod scanner;
+
+use std::{
+    io::Read,
+    sync::{Arc, RwLock},
+    time::Instant,
+};
+
+use bytes::Bytes;
+use tokio::sync::mpsc::Sender;
+use vt100::Parser;
+
+use crate::app::copy::clipboard;
+use crate::app::scroll::history_len;
+use crate::config::ClipboardPolicy;
+use gate::{Gate, SLICE};
+use images::Images;
+use kitty::KittyKeyboard;
+use links::Links;
+use scanner::{Scanner, Sequence, parse_params};
+
+// What uncl knows about a PTY beyond the vt100 screen, filled in by its tap
+#[derive(Default)]
+pub struct PtyState {
+    pub kitty: KittyKeyboard,
+    // Set with OSC 0 or 2
+    pub title: Option<String>,
+    // Rang since the container last looked
+    pub bell: bool,
+    // DECSCUSR shape, 0 is whatever the host defaults to
+    pub cursor_shape: u8,
+    // Mode 1004, the app wants `CSI I` and `CSI O` on focus changes
+    pub focus_reporting: bool,
+    // Mode 2026, set while the app is in the middle of a frame
+    pub sync_since: Option<Instant>,
+    // OSC 52 copies on their way to the host, e.g. "c;aGVsbG8="
+    pub clipboard: Vec<String>,
+    // Rows of scrollback in reach, measured after every slice so the UI
+    // can read it without moving the view
+    pub history: usize,
+    pub links: Links,
+    pub images: Images,
+}
+
+// Under a flood every read fills the buffer, so each takes in all that's
+// waiting at once
+const READ_SIZE: usize = 64 * 1024;
+
+// Sits in a PTY reader loop, feeds the output to vt100 and watches it for
+// the sequences vt100 ignores, answering the ones that expect a reply
+pub struct Tap {
+    scanner: Scanner,
+    parser: Arc<RwLock<Parser>>,
+    state: Arc<RwLock<PtyState>>,
+    replies: Sender<Bytes>,
+    gate: Arc<Gate>,
+    clipboard: ClipboardPolicy,
+}
+
+impl Tap {
+    pub fn new(
+        parser: Arc<RwLock<Parser>>,
+        state: Arc<RwLock<PtyState>>,
+        replies: Sender<Bytes>,
+        gate: Arc<Gate>,
+        clipboard: ClipboardPolicy,
+    ) -> Self {
+        Self {
+            scanner: Scanner::new(),
+            parser,
+            state,
+            replies,
+            gate,
+            clipboard,
+        }
+    }
+
+    // Reads a PTY until it closes, then says so on `status`
+    pub fn run(mut self, mut reader: Box<dyn Read + Send>, status: Sender<bool>) {
+        let mut buf = vec![0u8; READ_SIZE];
+        loop {
+            match reader.read(&mut buf) {
+                // EOF, the process on the other end has ended
+                Ok(0) => break,
+                Ok(size) => self.feed(&buf[..size]),
+                Err(e) => {
+                    eprintln!("Read error: {}", e);
+                    break;
+                }
+            }
+        }
+        let _ = status.blocking_send(true);
+    }
+
+    // Runs output through vt100 a slice at a time, letting the UI in
+    // between slices
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for slice in bytes.chunks(SLICE) {
+            self.gate.wait();
+            self.feed_slice(slice);
+        }
+        self.gate.changed();
+    }
+
+    fn feed_slice(&mut self, bytes: &[u8]) {
+        let mut reply = Vec::new();
+        {
+            let mut parser = self.parser.write().unwrap();
+            let mut state = self.state.write().unwrap();
+            let mut done = 0;
+            for (i, &byte) in bytes.iter().enumerate() {
+                let text = byte != 0x1b && !self.scanner.in_sequence();
+                if let Some(sequence) = self.scanner.advance(byte) {
+                    // Catch the screen up first, so answers describe it as
+                    // it was when the question was asked
+                    parser.process(&bytes[done..=i]);
+                    done = i + 1;
+                    handle(
+                        &mut state,
+                        &mut parser,
+                        sequence,
+                        self.clipboard,
+                        &mut reply,
+                    );
+                } else if text
+                    && state.links.is_open()
+                    && let Some(tag) = state.links.tag_after(byte)
+                {
+                    parser.process(&bytes[done..=i]);
+                    parser.process(tag);
+                    done = i + 1;
+                }
+            }
+            parser.process(&bytes[done..]);
+            state.history = history_len(&mut parser);
+        }
+
+        if !reply.is_empty() && self.replies.blocking_send(Bytes::from(reply)).is_err() {
+            eprintln!("Failed to answer PTY query");
+        }
+    }
+}
+
+fn handle(
+    state: &mut PtyState,
+    parser: &mut Parser,
+    sequence: Sequence,
+    clipboard: ClipboardPolicy,
+    reply: &mut Vec<u8>,
+) {
+    match sequence {
+        Sequence::Csi {
+            params,
+            intermediates: [],
+            action: b'u',
+        } => {
+            let (marker, values) = parse_params(params);
+            let first = values.first().copied().unwrap_or(0);
+            match marker {
+                Some(b'>') => state.kitty.push(first),
+                Some(b'<') => state.kitty.pop(first),
+                Some(b'=') => state.kitty.set(first, values.get(1).copied().unwrap_or(1)),
+                Some(b'?') => {
+                    reply.extend_from_slice(format!("\x1b[?{}u", state.kitty.flags()).as_bytes())
+                }
+                _ => {}
+            }
+        }
+
+        Sequence::Csi {
+            params,
+            intermediates: [],
+            action: action @ (b'h' | b'l'),
+        } => {
+            let (marker, values) = parse_params(params);
+            if marker == Some(b'?') {
+                for mode in values {
+                    match mode {
+                        47 | 1047 | 1049 => state.kitty.set_alternate(action == b'h'),
+                        1004 => state.focus_reporting = action == b'h',
+                        2026 if action == b'h' => {
+                            state.sync_since.get_or_insert_with(Instant::now);
+                        }
+                        2026 => state.sync_since = None,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        // DSR: `CSI 5 n` asks for status, `CSI 6 n` and `CSI ? 6 n` for the
+        // cursor position
+        Sequence::Csi {
+            params,
+            intermediates: [],
+            action: b'n',
+        } => {
+            let (marker, values) = parse_params(params);
+            let (row, col) = parser.screen().cursor_position();
+            // A pending wrap leaves vt100's cursor one past the last column
+            let col = col.min(parser.screen().size().1.saturating_sub(1));
+            match (marker, values.as_slice()) {
+                (None, [5]) => reply.extend_from_slice(b"\x1b[0n"),
+                (None, [6]) => {
+                    reply.extend_from_slice(format!("\x1b[{};{}R", row + 1, col + 1).as_bytes())
+                }
+                (Some(b'?'), [6]) => {
+                    reply.extend_from_slice(format!("\x1b[?{};{}R", row + 1, col + 1).as_bytes())
+                }
+                _ => {}
+            }
+        }
+
+        // DA1 and DA2: a VT220 with ANSI color, and uncl with its version
+        Sequence::Csi {
+            params,
+            intermediates: [],
+            action: b'c',
+        } => match parse_params(params) {
+            (None, values) if values.iter().all(|&v| v == 0) => {
+                reply.extend_from_slice(b"\x1b[?62;22c")
+            }
+            (Some(b'>'), values) if values.iter().all(|&v| v == 0) => {
+                reply.extend_from_slice(format!("\x1b[>1;{};0c", version_number()).as_bytes())
+            }
+            _ => {}
+        },
+
+        // DECSCUSR
+        Sequence::Csi {
+            params,
+            intermediates: b" ",
+            action: b'q',
+        } => {
+            let (_, values) = parse_params(params);
+            if let shape @ 0..=6 = values.first().copied().unwrap_or(0) {
+                state.cursor_shape = shape as u8;
+            }
+        }
+
+        // XTVERSION
+        Sequence::Csi {
+            params,
+            intermediates: [],
+            action: b'q',
+        } => {
+            let (marker, values) = parse_params(params);
+            if marker == Some(b'>') && values.iter().all(|&v| v == 0) {
+                let version = env!("CARGO_PKG_VERSION");
+                reply.extend_from_slice(format!("\x1bP>|uncl({})\x1b\\", version).as_bytes());
+            }
+        }
+
+        Sequence::Bell => state.bell = true,
+
+        Sequence::Osc(payload) if payload.starts_with(b"8;") => state.links.set(&payload[2..]),
+
+        Sequence::Dcs(payload) => state.images.sixel(parser, payload),
+        Sequence::Apc(payload) => state.images.kitty(parser, payload, reply),
+
+        Sequence::Osc(payload) if payload.starts_with(b"0;") || payload.starts_with(b"2;") => {
+            state.title = Some(String::from_utf8_lossy(&payload[2..]).into_owned());
+        }
+
+        // OSC 52: copies queue up for the host, reads get what uncl last
+        // put on the clipboard since the host's own answer can't come back
+        Sequence::Osc(payload) if payload.starts_with(b"52;") => {
+            let text = String::from_utf8_lossy(&payload[3..]);
+            let Some((target, data)) = text.split_once(';') else {
+                return;
+            };
+            match (clipboard, data) {
+                (ClipboardPolicy::Deny, _) | (ClipboardPolicy::WriteOnly, "?") => {}
+                (ClipboardPolicy::Allow, "?") => {
+                    let last = clipboard::last();
+                    reply
+                        .extend_from_slice(format!("\x1b]52;{};{}\x1b\\", target, last).as_bytes());
+                }
+                _ => state.clipboard.push(text.into_owned()),
+            }
+        }
+
+        // OSC 10 and 11 queries get the host's own colors, when it told us
+        Sequence::Osc(payload) => {
+            let colors = host::colors();
+            let color = match payload {
+                b"10;?" => colors.foreground.as_ref(),
+                b"11;?" => colors.background.as_ref(),
+                _ => None,
+            };
+            if let Some(color) = color {
+                let code = &payload[..2];
+                reply.extend_from_slice(b"\x1b]");
+                reply.extend_from_slice(code);
+                reply.extend_from_slice(format!(";{}\x1b\\", color).as_bytes());
+            }
+        }
+
+        // RIS puts everything back to power-on defaults
+        Sequence::Esc {
+            intermediates: [],
+            byte: b'c',
+        } => {
+            // A reset doesn't take back the title or copies still on their
+            // way to the host
+            let title = state.title.take();
+            let copies = std::mem::take(&mut state.clipboard);
+            let mut images = std::mem::take(&mut state.images);
+            images.clear();
+            *state = PtyState::default();
+            state.title = title;
+            state.clipboard = copies;
+            state.images = images;
+        }
+
+        _ => {}
+    }
+}
+
+// 0.1.0 reads as 100, the way xterm reports its patch level
+fn version_number() -> u32 {
+    env!("CARGO_PKG_VERSION")
+        .split('.')
+        .take(3)
+        .fold(0, |n, part| n * 100 + part.parse::<u32>().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::sync::mpsc::{Receiver, channel};
+
+    use crate::app::copy::history::History;
+    use crate::app::scroll;
+
+    fn tap(rows: u16, cols: u16) -> (Tap, Receiver<Bytes>) {
+        tap_with(rows, cols, ClipboardPolicy::WriteOnly)
+    }
+
+    fn tap_with(rows: u16, cols: u16, clipboard: ClipboardPolicy) -> (Tap, Receiver<Bytes>) {
+        let (tx, rx) = channel(16);
+        let tap = Tap::new(
+            Arc::new(RwLock::new(Parser::new(rows, cols, 0))),
+            Arc::new(RwLock::new(PtyState::default())),
+            tx,
+            Arc::new(Gate::default()),
+            clipboard,
+        );
+        (tap, rx)
+    }
+
+    #[test]
+    fn reports_the_cursor_on_screen() {
+        let (mut tap, mut rx) = tap(5, 10);
+
+        tap.feed(b"\x1b[3;4H\x1b[6n");
+        assert_eq!(rx.try_recv().unwrap(), "\x1b[3;4R");
+
+        // Filling the last column leaves a wrap pending
+        tap.feed(b"\r0123456789\x1b[6n\x1b[?6n");
+        assert_eq!(rx.try_recv().unwrap(), "\x1b[3;10R\x1b[?3;10R");
+    }
+
+    #[test]
+    fn tracks_synchronized_updates() {
+        let (mut tap, _rx) = tap(5, 10);
+        let state = tap.state.clone();
+        let since = || state.read().unwrap().sync_since;
+
+        tap.feed(b"\x1b[?2026h");
+        let started = since().expect("update started");
+        // Starting again doesn't push the deadline back
+        tap.feed(b"a\x1b[?2026h");
+        assert_eq!(since(), Some(started));
+        tap.feed(b"\x1b[?2026l");
+        assert_eq!(since(), None);
+
+        // A reset ends it too
+        tap.feed(b"\x1b[?1;2026h");
+        assert!(since().is_some());
+        tap.feed(b"\x1bc");
+        assert_eq!(since(), None);
+    }
+
+    #[test]
+    fn answers_kitty_keyboard_queries() {
+        let (mut tap, mut rx) = tap(5, 10);
+
+        tap.feed(b"\x1b[?u");
+        assert_eq!(rx.try_recv().unwrap(), "\x1b[?0u");
+        tap.feed(b"\x1b[>1u\x1b[=8;2u\x1b[?u");
+        assert_eq!(rx.try_recv().unwrap(), "\x1b[?9u");
+
+        // Apps on the alternate screen get a stack of their own
+        tap.feed(b"\x1b[?1049h\x1b[?u\x1b[>31u\x1b[=1;3u\x1b[?u");
+        assert_eq!(rx.try_recv().unwrap(), "\x1b[?0u\x1b[?30u");
+        tap.feed(b"\x1b[?1049l\x1b[<u\x1b[?u");
+        assert_eq!(rx.try_recv().unwrap(), "\x1b[?0u");
+    }
+
+    #[test]
+    fn tracks_the_cursor_shape() {
+        let (mut tap, _rx) = tap(5, 10);
+        let shape = |tap: &Tap| tap.state.read().unwrap().cursor_shape;
+
+        tap.feed(b"\x1b[5 q");
+        assert_eq!(shape(&tap), 5);
+        // Shapes past the last one are ignored
+        tap.feed(b"\x1b[7 q");
+        assert_eq!(shape(&tap), 5);
+        tap.feed(b"\x1b[ q");
+        assert_eq!(shape(&tap), 0);
+        tap.feed(b"\x1b[2 q\x1bc");
+        assert_eq!(shape(&tap), 0);
+    }
+
+    #[test]
+    fn reset_keeps_pending_copies() {
+        let (mut tap, _rx) = tap(5, 10);
+
+        tap.feed(b"\x1b]2;title\x07\x1b]52;c;aGVsbG8=\x07\x1b[?1004h\x1bc");
+        let state = tap.state.read().unwrap();
+        assert_eq!(state.clipboard, ["c;aGVsbG8="]);
+        assert_eq!(state.title.as_deref(), Some("title"));
+        assert!(!state.focus_reporting);
+    }
+
+    #[test]
+    fn follows_the_clipboard_policy() {
+        // Nothing else in the tests puts anything on the clipboard
+        let last = format!("\x1b]52;p;{}\x1b\\", clipboard::last());
+        let cases = [
+            (ClipboardPolicy::Deny, &[][..], None),
+            (ClipboardPolicy::WriteOnly, &["c;aGVsbG8="][..], None),
+            (ClipboardPolicy::Allow, &["c;aGVsbG8="][..], Some(last)),
+        ];
+        for (policy, copies, answer) in cases {
+            let (mut tap, mut rx) = tap_with(5, 10, policy);
+            // A copy, a read, and one with no data at all
+            tap.feed(b"\x1b]52;c;aGVsbG8=\x07\x1b]52;p;?\x1b\\\x1b]52;c\x07");
+            assert_eq!(tap.state.read().unwrap().clipboard, copies, "{:?}", policy);
+            assert_eq!(rx.try_recv().ok(), answer.map(Bytes::from), "{:?}", policy);
+        }
+    }
+
+    fn link_at(tap: &Tap, row: u16, col: u16) -> Option<String> {
+        let parser = tap.parser.read().unwrap();
+        let state = tap.state.read().unwrap();
+        let link = state.links.at(parser.screen(), row, col)?;
+        Some(links::target(link).to_string())
+    }
+
+    #[test]
+    fn links_belong_to_cells() {
+        let (mut tap, _rx) = tap(5, 20);
+
+        // Two identical rows, only the second is a link
+        tap.feed(b"$ ls\r\n$ \x1b]8;;file:///ls\x1b\\ls\x1b]8;;\x1b\\\r\n");
+        assert_eq!(link_at(&tap, 0, 2), None);
+        assert_eq!(link_at(&tap, 1, 1), None);
+        assert_eq!(link_at(&tap, 1, 2).as_deref(), Some("file:///ls"));
+        assert_eq!(link_at(&tap, 1, 3).as_deref(), Some("file:///ls"));
+        assert_eq!(link_at(&tap, 1, 4), None);
+
+        // Writing over part of it takes the link off just those cells
+        tap.feed(b"\x1b[2;4HS");
+        assert_eq!(link_at(&tap, 1, 2).as_deref(), Some("file:///ls"));
+        assert_eq!(link_at(&tap, 1, 3), None);
+
+        // Copy mode and search read the text without the tags
+        let mut parser = tap.parser.write().unwrap();
+        let cells = History::new(&mut parser).cells(1);
+        assert_eq!(cells[..5].concat(), "$ lS ");
+    }
+
+    #[test]
+    fn links_scroll_into_history() {
+        let (mut tap, _rx) = tap(3, 10);
+        {
+            let mut parser = tap.parser.write().unwrap();
+            *parser = Parser::new(3, 10, 10);
+        }
+
+        // Split across reads, wrapping, with a wide character
+        tap.feed(b"\x1b]8;id=1;http://x\x1b\\abcdefgh\xe4\xb8");
+        tap.feed(b"\xadij\x1b]8;;\x07\r\n\r\n\r\n\r\n");
+        scroll::scroll_by(&tap.parser, 4);
+
+        assert_eq!(link_at(&tap, 0, 0).as_deref(), Some("http://x"));
+        assert_eq!(link_at(&tap, 0, 8).as_deref(), Some("http://x"));
+        assert_eq!(link_at(&tap, 0, 9).as_deref(), Some("http://x"));
+        assert_eq!(link_at(&tap, 1, 1).as_deref(), Some("http://x"));
+        assert_eq!(link_at(&tap, 1, 2), None);
+    }
+}
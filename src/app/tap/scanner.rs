@@ -0,0 +1,343 @@
+// A small escape sequence scanner that runs alongside vt100. It only finds
+// sequence boundaries, so the tap can act on the ones vt100 drops, and it
+// keeps string payloads whole no matter how the PTY reads split them.
+
+// Payload caps so a broken stream can't grow the buffers forever
+const MAX_PARAMS: usize = 256;
+const MAX_STRING: usize = 16 * 1024 * 1024;
+
+const BEL: u8 = 0x07;
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1a;
+const ESC: u8 = 0x1b;
+
+pub enum Sequence<'a> {
+    Bell,
+    Esc {
+        intermediates: &'a [u8],
+        byte: u8,
+    },
+    // `params` keeps the private marker, e.g. b">1" for `CSI > 1 u`
+    Csi {
+        params: &'a [u8],
+        intermediates: &'a [u8],
+        action: u8,
+    },
+    Osc(&'a [u8]),
+    // The parameters and data together, e.g. b"0;1q#0~~" for a sixel image
+    Dcs(&'a [u8]),
+    Apc(&'a [u8]),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StringKind {
+    Osc,
+    Dcs,
+    Apc,
+    // SOS and PM are parsed so their payload isn't mistaken for output
+    Ignored,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+    String(StringKind),
+    // Saw ESC inside a string, waiting for the `\` of ST
+    StringEscape(StringKind),
+}
+
+pub struct Scanner {
+    state: State,
+    params: Vec<u8>,
+    intermediates: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Self {
+            state: State::Ground,
+            params: Vec::new(),
+            intermediates: Vec::new(),
+            payload: Vec::new(),
+        }
+    }
+
+    // Whether the bytes so far belong to an unfinished sequence
+    pub fn in_sequence(&self) -> bool {
+        self.state != State::Ground
+    }
+
+    // Inside a string, where ESC starts its terminator rather than a new
+    // sequence
+    pub fn in_string(&self) -> bool {
+        matches!(self.state, State::String(_) | State::StringEscape(_))
+    }
+
+    pub fn advance(&mut self, byte: u8) -> Option<Sequence<'_>> {
+        match self.state {
+            State::Ground => match byte {
+                ESC => self.enter_escape(),
+                BEL => return Some(Sequence::Bell),
+                _ => {}
+            },
+
+            State::Escape => match byte {
+                CAN | SUB => self.state = State::Ground,
+                ESC => self.enter_escape(),
+                0x20..=0x2f => self.push_intermediate(byte),
+                b'[' if self.intermediates.is_empty() => self.state = State::Csi,
+                b']' if self.intermediates.is_empty() => self.enter_string(StringKind::Osc),
+                b'P' if self.intermediates.is_empty() => self.enter_string(StringKind::Dcs),
+                b'_' if self.intermediates.is_empty() => self.enter_string(StringKind::Apc),
+                b'X' | b'^' if self.intermediates.is_empty() => {
+                    self.enter_string(StringKind::Ignored)
+                }
+                0x30..=0x7e => {
+                    self.state = State::Ground;
+                    return Some(Sequence::Esc {
+                        intermediates: &self.intermediates,
+                        byte,
+                    });
+                }
+                // Other C0 controls execute in the middle of a sequence
+                _ => {}
+            },
+
+            State::Csi => match byte {
+                CAN | SUB => self.state = State::Ground,
+                ESC => self.enter_escape(),
+                0x30..=0x3f if self.params.len() < MAX_PARAMS => self.params.push(byte),
+                0x30..=0x3f => {}
+                0x20..=0x2f => self.push_intermediate(byte),
+                0x40..=0x7e => {
+                    self.state = State::Ground;
+                    return Some(Sequence::Csi {
+                        params: &self.params,
+                        intermediates: &self.intermediates,
+                        action: byte,
+                    });
+                }
+                _ => {}
+            },
+
+            State::String(kind) => match byte {
+                ESC => self.state = State::StringEscape(kind),
+                // OSC may also end with BEL
+                BEL if kind == StringKind::Osc => {
+                    self.state = State::Ground;
+                    return Some(Sequence::Osc(&self.payload));
+                }
+                CAN | SUB => self.state = State::Ground,
+                _ => {
+                    if kind != StringKind::Ignored && self.payload.len() < MAX_STRING {
+                        self.payload.push(byte);
+                    }
+                }
+            },
+
+            State::StringEscape(kind) => {
+                if byte == b'\\' {
+                    self.state = State::Ground;
+                    return match kind {
+                        StringKind::Osc => Some(Sequence::Osc(&self.payload)),
+                        StringKind::Dcs => Some(Sequence::Dcs(&self.payload)),
+                        StringKind::Apc => Some(Sequence::Apc(&self.payload)),
+                        StringKind::Ignored => None,
+                    };
+                }
+
+                // Any other ESC aborts the string and starts a new sequence
+                self.enter_escape();
+                return self.advance(byte);
+            }
+        }
+
+        None
+    }
+
+    fn enter_escape(&mut self) {
+        self.state = State::Escape;
+        self.params.clear();
+        self.intermediates.clear();
+    }
+
+    fn enter_string(&mut self, kind: StringKind) {
+        self.state = State::String(kind);
+        self.payload.clear();
+    }
+
+    fn push_intermediate(&mut self, byte: u8) {
+        if self.intermediates.len() < MAX_PARAMS {
+            self.intermediates.push(byte);
+        }
+    }
+}
+
+// Splits `CSI` parameters into the private marker and numeric values, a
+// missing value reads as 0 and sub-parameters after `:` are dropped
+pub fn parse_params(params: &[u8]) -> (Option<u8>, Vec<u32>) {
+    let (marker, rest) = match params.first() {
+        Some(&m @ (b'<' | b'=' | b'>' | b'?')) => (Some(m), &params[1..]),
+        _ => (None, params),
+    };
+
+    if rest.is_empty() {
+        return (marker, Vec::new());
+    }
+
+    let values = rest
+        .split(|&b| b == b';')
+        .map(|field| {
+            field
+                .iter()
+                .take_while(|b| b.is_ascii_digit())
+                .fold(0u32, |n, b| {
+                    n.saturating_mul(10).saturating_add((b - b'0') as u32)
+                })
+        })
+        .collect();
+
+    (marker, values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sequences rendered to strings so they outlive the scanner borrow
+    fn scan(scanner: &mut Scanner, bytes: &[u8], seen: &mut Vec<String>) {
+        let text = |b: &[u8]| String::from_utf8_lossy(b).into_owned();
+        for &byte in bytes {
+            let Some(sequence) = scanner.advance(byte) else {
+                continue;
+            };
+            seen.push(match sequence {
+                Sequence::Bell => "bel".to_string(),
+                Sequence::Esc {
+                    intermediates,
+                    byte,
+                } => format!("esc {}{}", text(intermediates), byte as char),
+                Sequence::Csi {
+                    params,
+                    intermediates,
+                    action,
+                } => format!(
+                    "csi {} {}{}",
+                    text(params),
+                    text(intermediates),
+                    action as char
+                ),
+                Sequence::Osc(payload) => format!("osc {}", text(payload)),
+                Sequence::Dcs(payload) => format!("dcs {}", text(payload)),
+                Sequence::Apc(payload) => format!("apc {}", text(payload)),
+            });
+        }
+    }
+
+    fn scan_all(bytes: &[u8]) -> Vec<String> {
+        let mut seen = Vec::new();
+        scan(&mut Scanner::new(), bytes, &mut seen);
+        seen
+    }
+
+    const STREAM: &[u8] = b"a\x07b\x1b(B\x1b7\x1b[?1;2h\x1b[ q\x1b]0;title\x07\
+        \x1b]8;;http://x\x1b\\\x1bPq#0~\x1b\\\x1b_Gf=100;AAAA\x1b\\\x1bXsos\x1b\\\x1b[m";
+
+    #[test]
+    fn finds_sequences() {
+        assert_eq!(
+            scan_all(STREAM),
+            [
+                "bel",
+                "esc (B",
+                "esc 7",
+                "csi ?1;2 h",
+                "csi   q",
+                "osc 0;title",
+                "osc 8;;http://x",
+                "dcs q#0~",
+                "apc Gf=100;AAAA",
+                "csi  m",
+            ]
+        );
+    }
+
+    #[test]
+    fn survives_every_split() {
+        let whole = scan_all(STREAM);
+        for at in 0..=STREAM.len() {
+            let mut scanner = Scanner::new();
+            let mut seen = Vec::new();
+            scan(&mut scanner, &STREAM[..at], &mut seen);
+            scan(&mut scanner, &STREAM[at..], &mut seen);
+            assert_eq!(seen, whole, "split at {}", at);
+        }
+    }
+
+    #[test]
+    fn ends_osc_with_bel_or_st() {
+        assert_eq!(scan_all(b"\x1b]2;a\x07"), ["osc 2;a"]);
+        assert_eq!(scan_all(b"\x1b]2;a\x1b\\"), ["osc 2;a"]);
+        // BEL only ends OSC, elsewhere it's part of the payload
+        assert_eq!(scan_all(b"\x1bPa\x07b\x1b\\"), ["dcs a\x07b"]);
+        // A BEL inside an ignored string doesn't ring
+        assert_eq!(scan_all(b"\x1b^a\x07b\x1b\\\x07"), ["bel"]);
+    }
+
+    #[test]
+    fn aborts_broken_sequences() {
+        // CAN and SUB cancel, the rest is plain output
+        assert_eq!(scan_all(b"\x1b[1\x18m\x1b]0;x\x1a\x07"), ["bel"]);
+        // ESC inside a string starts a new sequence and drops the string
+        assert_eq!(scan_all(b"\x1b]0;x\x1b[2J"), ["csi 2 J"]);
+        // A fresh ESC mid-CSI starts over
+        assert_eq!(scan_all(b"\x1b[12\x1b[3m"), ["csi 3 m"]);
+        // C0 controls inside CSI are executed, not part of it
+        assert_eq!(scan_all(b"\x1b[1\n2m"), ["csi 12 m"]);
+    }
+
+    #[test]
+    fn caps_params_and_payloads() {
+        let mut csi = b"\x1b[".to_vec();
+        csi.extend(std::iter::repeat_n(b'1', MAX_PARAMS * 4));
+        csi.push(b'm');
+        let seen = scan_all(&csi);
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].len(), "csi  m".len() + MAX_PARAMS);
+
+        let mut osc = b"\x1b]".to_vec();
+        osc.extend(std::iter::repeat_n(b'x', MAX_STRING + 10));
+        osc.push(BEL);
+        let seen = scan_all(&osc);
+        assert_eq!(seen[0].len(), "osc ".len() + MAX_STRING);
+    }
+
+    #[test]
+    fn parses_params() {
+        let cases: &[(&[u8], Option<u8>, &[u32])] = &[
+            (b"", None, &[]),
+            (b"?", Some(b'?'), &[]),
+            (b"1;2", None, &[1, 2]),
+            (b">1", Some(b'>'), &[1]),
+            // Missing values read as 0
+            (b";5;", None, &[0, 5, 0]),
+            // Sub-parameters and garbage after the digits are dropped
+            (b"38:2:1:2:3;4", None, &[38, 4]),
+            (b"1x;2", None, &[1, 2]),
+            (b"=x", Some(b'='), &[0]),
+            // Overlong values saturate instead of wrapping
+            (b"99999999999999999999;1", None, &[u32::MAX, 1]),
+        ];
+        for (params, marker, values) in cases {
+            assert_eq!(
+                parse_params(params),
+                (*marker, values.to_vec()),
+                "{:?}",
+                String::from_utf8_lossy(params)
+            );
+        }
+    }
+}
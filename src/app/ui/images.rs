@@ -0,0 +1,306 @@
+use std::{collections::HashSet, io};
+
+use ratatui::{backend::Backend, buffer::Buffer, layout::Rect};
+use vt100::Screen;
+
+use crate::app::tap::images::{Graphic, Image, Images, marker_id};
+
+// Base64 sent per kitty command, the most the protocol takes at once
+const KITTY_CHUNK: usize = 4096;
+
+// Where an image of a pane lands on the host and the part of it that shows
+#[derive(Clone, PartialEq)]
+pub struct Placement {
+    pub image: Image,
+    pub x: u16,
+    pub y: u16,
+    // Rows cut off the top, then the rows and columns left
+    pub skip: u16,
+    pub rows: u16,
+    pub cols: u16,
+}
+
+impl Placement {
+    fn whole(&self) -> bool {
+        self.skip == 0 && self.rows == self.image.rows && self.cols == self.image.cols
+    }
+
+    fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.cols, self.rows)
+    }
+}
+
+// The images of a pane drawn at `area`, found by their markers. Parts the
+// area cuts off are cropped away, or the whole image is left out when the
+// host can't crop it, and anything `hole` touches is left out.
+pub fn placements(
+    screen: &Screen,
+    images: &Images,
+    area: Rect,
+    hole: Option<Rect>,
+) -> Vec<Placement> {
+    let mut placements: Vec<Placement> = Vec::new();
+    if images.is_empty() {
+        return placements;
+    }
+
+    let (rows, cols) = screen.size();
+    for row in 0..area.height.min(rows) {
+        for col in 0..area.width.min(cols) {
+            let Some(id) = screen
+                .cell(row, col)
+                .and_then(|cell| marker_id(&cell.contents()))
+            else {
+                continue;
+            };
+            let y = area.y + row;
+            if let Some(placement) = placements.iter_mut().find(|p| p.image.id == id) {
+                if y >= placement.y + placement.rows {
+                    placement.rows += 1;
+                }
+                continue;
+            }
+            let Some(image) = images.get(id) else {
+                continue;
+            };
+            placements.push(Placement {
+                x: area.x + col,
+                y,
+                skip: 0,
+                rows: 1,
+                cols: image.cols.min(area.width - col),
+                image: image.clone(),
+            });
+        }
+    }
+
+    for placement in &mut placements {
+        // Only an image scrolled partly off the top starts on the first row
+        // with rows missing
+        if placement.y == area.y {
+            placement.skip = placement.image.rows.saturating_sub(placement.rows);
+        }
+    }
+    placements.retain(|placement| {
+        let croppable = matches!(placement.image.graphic, Graphic::Kitty { .. })
+            && placement.image.pixels.is_some();
+        (placement.whole() || croppable)
+            && !hole.is_some_and(|hole| hole.intersects(placement.rect()))
+    });
+    placements
+}
+
+// The markers have no business on the host, they're drawn as blanks
+pub fn blank(buf: &mut Buffer, area: Rect) {
+    for y in area.top()..area.bottom() {
+        for x in area.left()..area.right() {
+            if let Some(cell) = buf.cell_mut((x, y))
+                && marker_id(cell.symbol()).is_some()
+            {
+                cell.set_symbol(" ");
+            }
+        }
+    }
+}
+
+// Puts the images on the host after a frame. Sixel goes out whole every
+// time, kitty images are sent once and placed by id after that.
+pub fn show<B: Backend + io::Write>(
+    backend: &mut B,
+    placements: &[Placement],
+    sent: &mut HashSet<u32>,
+) -> io::Result<()> {
+    if placements.is_empty() {
+        return Ok(());
+    }
+
+    // Save and restore the cursor around it, the frame already placed it
+    backend.write_all(b"\x1b7")?;
+    for placement in placements {
+        let image = &placement.image;
+        backend.set_cursor_position((placement.x, placement.y))?;
+        match &image.graphic {
+            Graphic::Sixel(sequence) => backend.write_all(sequence)?,
+            Graphic::Kitty { keys, data } => {
+                if sent.insert(image.id) {
+                    transmit(backend, image.id, keys, data)?;
+                }
+                write!(
+                    backend,
+                    "\x1b_Ga=p,i={},p=1,q=2,C=1,c={},r={}{}\x1b\\",
+                    image.id,
+                    placement.cols,
+                    placement.rows,
+                    crop(placement)
+                )?;
+            }
+        }
+    }
+    backend.write_all(b"\x1b8")?;
+    Ok(())
+}
+
+// Takes down the kitty images shown last frame that aren't in this one,
+// sixel is wiped by redrawing the cells under it
+pub fn hide<B: Backend + io::Write>(
+    backend: &mut B,
+    shown: &[Placement],
+    placements: &[Placement],
+) -> io::Result<()> {
+    for placement in shown {
+        if matches!(placement.image.graphic, Graphic::Kitty { .. })
+            && !placements.iter().any(|p| p.image == placement.image)
+        {
+            write!(backend, "\x1b_Ga=d,d=i,i={},q=2\x1b\\", placement.image.id)?;
+        }
+    }
+    Ok(())
+}
+
+// Frees kitty images on the host
+pub fn forget<W: io::Write>(out: &mut W, ids: impl IntoIterator<Item = u32>) -> io::Result<()> {
+    for id in ids {
+        write!(out, "\x1b_Ga=d,d=I,i={},q=2\x1b\\", id)?;
+    }
+    out.flush()
+}
+
+fn transmit<B: Backend + io::Write>(
+    backend: &mut B,
+    id: u32,
+    keys: &str,
+    data: &str,
+) -> io::Result<()> {
+    let keys = if keys.is_empty() {
+        String::new()
+    } else {
+        format!(",{}", keys)
+    };
+    // Base64 is all ASCII, so it splits anywhere
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    if chunks.is_empty() {
+        return write!(backend, "\x1b_Ga=t,i={},q=2{}\x1b\\", id, keys);
+    }
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        if i == 0 {
+            write!(backend, "\x1b_Ga=t,i={},q=2{},m={};", id, keys, more)?;
+        } else {
+            write!(backend, "\x1b_Gm={};", more)?;
+        }
+        backend.write_all(chunk)?;
+        backend.write_all(b"\x1b\\")?;
+    }
+    Ok(())
+}
+
+// The source rectangle for a cropped kitty image, in its own pixels
+fn crop(placement: &Placement) -> String {
+    let image = &placement.image;
+    match image.pixels {
+        Some((width, height)) if !placement.whole() => {
+            let scale = |n: u16, total: u32, of: u16| n as u64 * total as u64 / of.max(1) as u64;
+            format!(
+                ",x=0,y={},w={},h={}",
+                scale(placement.skip, height, image.rows),
+                scale(placement.cols, width, image.cols),
+                scale(placement.rows, height, image.rows)
+            )
+        }
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use vt100::Parser;
+
+    // A screen with kitty images put at the cursor, each sized `c`x`r`
+    // cells and `s`x`v` pixels
+    fn screen(rows: u16, commands: &[&str]) -> (Parser, Images) {
+        let mut parser = Parser::new(rows, 20, 0);
+        let mut images = Images::default();
+        for command in commands {
+            match command.strip_prefix('G') {
+                Some(_) => images.kitty(&mut parser, command.as_bytes(), &mut Vec::new()),
+                None => parser.process(command.as_bytes()),
+            }
+        }
+        (parser, images)
+    }
+
+    fn found(placed: &[Placement]) -> Vec<(u16, u16, u16, u16, u16)> {
+        placed
+            .iter()
+            .map(|p| (p.x, p.y, p.skip, p.rows, p.cols))
+            .collect()
+    }
+
+    const IMAGE: &str = "Ga=T,f=32,s=40,v=60,c=4,r=3;AAAA";
+
+    #[test]
+    fn places_whole_images() {
+        let (parser, images) = screen(10, &["\x1b[2;3H", IMAGE]);
+        let area = Rect::new(5, 1, 20, 10);
+        let placed = placements(parser.screen(), &images, area, None);
+        assert_eq!(found(&placed), [(7, 2, 0, 3, 4)]);
+        assert!(placed[0].whole());
+        assert_eq!(crop(&placed[0]), "");
+
+        // Anything touching the hole is left out
+        let hole = Rect::new(10, 4, 5, 5);
+        assert!(placements(parser.screen(), &images, area, Some(hole)).is_empty());
+        let hole = Rect::new(11, 4, 5, 5);
+        assert_eq!(
+            placements(parser.screen(), &images, area, Some(hole)).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn crops_images_cut_off() {
+        // Scrolled two rows off the top
+        let (parser, images) = screen(4, &["\x1b[3;1H", IMAGE, "\n\n\n"]);
+        let area = Rect::new(0, 0, 20, 4);
+        let placed = placements(parser.screen(), &images, area, None);
+        assert_eq!(found(&placed), [(0, 0, 2, 1, 4)]);
+        assert_eq!(crop(&placed[0]), ",x=0,y=40,w=40,h=20");
+
+        // Cut by the right edge of the area
+        let (parser, images) = screen(10, &["\x1b[1;19H", IMAGE]);
+        let area = Rect::new(0, 0, 20, 10);
+        let placed = placements(parser.screen(), &images, area, None);
+        // Only the columns that fit the screen were marked
+        assert_eq!(found(&placed), [(18, 0, 0, 3, 2)]);
+        assert_eq!(crop(&placed[0]), ",x=0,y=0,w=20,h=60");
+    }
+
+    #[test]
+    fn drops_what_cant_be_cropped() {
+        // Without pixels there's nothing to crop by
+        let (parser, images) = screen(4, &["\x1b[3;1H", "Ga=T,f=32,c=4,r=3;AAAA", "\n\n\n"]);
+        let area = Rect::new(0, 0, 20, 4);
+        assert!(placements(parser.screen(), &images, area, None).is_empty());
+    }
+
+    #[test]
+    fn crops_huge_images_without_overflow() {
+        let (parser, images) = screen(
+            4,
+            &[
+                "\x1b[3;1H",
+                "Ga=T,f=32,s=4000000000,v=4000000000,c=4,r=3;AAAA",
+                "\n\n\n",
+            ],
+        );
+        let area = Rect::new(0, 0, 20, 4);
+        let placed = placements(parser.screen(), &images, area, None);
+        assert_eq!(
+            crop(&placed[0]),
+            ",x=0,y=2666666666,w=4000000000,h=1333333333"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use std::{io, sync::Arc};
+
+use ratatui::{backend::Backend, buffer::Buffer, layout::Rect, text::Span};
+use vt100::Screen;
+
+use crate::app::tap::links::{self, Links};
+
+// A run of cells on the host that belongs to one OSC 8 link
+pub struct LinkRun {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub link: Arc<str>,
+}
+
+// Where the links of a pane drawn at `area` land on the host, leaving out
+// whatever `hole` covers
+pub fn runs(screen: &Screen, links: &Links, area: Rect, hole: Option<Rect>) -> Vec<LinkRun> {
+    let mut runs = Vec::new();
+    if links.is_empty() {
+        return runs;
+    }
+
+    let (rows, cols) = screen.size();
+    for row in 0..area.height.min(rows) {
+        let y = area.y + row;
+        let mut run: Option<LinkRun> = None;
+        for col in 0..area.width.min(cols) {
+            let x = area.x + col;
+            let Some(cell) = screen
+                .cell(row, col)
+                .filter(|_| !hole.is_some_and(|hole| hole.contains((x, y).into())))
+            else {
+                runs.extend(run.take());
+                continue;
+            };
+
+            // The second half of a wide character goes with the first
+            if cell.is_wide_continuation() {
+                if let Some(run) = &mut run {
+                    run.width += 1;
+                }
+                continue;
+            }
+
+            match (links.get(cell), &mut run) {
+                (Some(link), Some(run)) if run.link == *link => run.width += 1,
+                (link, _) => {
+                    runs.extend(run.take());
+                    run = link.map(|link| LinkRun {
+                        x,
+                        y,
+                        width: 1,
+                        link: link.clone(),
+                    });
+                }
+            }
+        }
+        runs.extend(run);
+    }
+    runs
+}
+
+// Takes the link tags back out of the cells of a pane drawn at `area`,
+// before the host sees them
+pub fn untag(buf: &mut Buffer, area: Rect) {
+    for y in area.top()..area.bottom() {
+        for x in area.left()..area.right() {
+            if let Some(cell) = buf.cell_mut((x, y))
+                && links::is_tagged(cell.symbol())
+            {
+                let symbol = links::untag(cell.symbol());
+                cell.set_symbol(&symbol);
+            }
+        }
+    }
+}
+
+// ratatui's buffer has no room for a link, so after a frame the linked
+// cells are printed again with OSC 8 around them
+pub fn reprint<B: Backend + io::Write>(
+    backend: &mut B,
+    buffer: &Buffer,
+    runs: &[LinkRun],
+) -> io::Result<()> {
+    if runs.is_empty() {
+        return Ok(());
+    }
+
+    // Save and restore the cursor around it, the frame already placed it
+    backend.write_all(b"\x1b7")?;
+    for run in runs {
+        let mut cells = Vec::new();
+        let mut skip = 0;
+        for x in run.x..run.x + run.width {
+            let Some(cell) = buffer.cell((x, run.y)) else {
+                break;
+            };
+            // The cells after a wide character are part of it
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            skip = Span::raw(cell.symbol()).width().saturating_sub(1);
+            cells.push((x, run.y, cell));
+        }
+
+        write!(backend, "\x1b]8;{}\x1b\\", run.link)?;
+        backend.draw(cells.into_iter())?;
+        backend.write_all(b"\x1b]8;;\x1b\\")?;
+    }
+    backend.write_all(b"\x1b8")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ratatui::widgets::Widget;
+    use vt100::Parser;
+
+    // Prints `text` under `link` the way the tap does
+    fn print(parser: &mut Parser, links: &mut Links, link: &str, text: &str) {
+        links.set(format!(";{}", link).as_bytes());
+        for byte in text.bytes() {
+            parser.process(&[byte]);
+            if let Some(tag) = links.tag_after(byte) {
+                parser.process(tag);
+            }
+        }
+        links.set(b";");
+    }
+
+    #[test]
+    fn finds_runs_by_cell() {
+        let mut parser = Parser::new(3, 10, 0);
+        let mut links = Links::default();
+        parser.process(b"ab cd\r\nab ");
+        print(&mut parser, &mut links, "http://x", "cd");
+        print(&mut parser, &mut links, "http://y", "中e");
+
+        let area = Rect::new(2, 1, 10, 3);
+        let found: Vec<_> = runs(parser.screen(), &links, area, None)
+            .iter()
+            .map(|run| (run.x, run.y, run.width, run.link.to_string()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (5, 2, 2, String::from(";http://x")),
+                (7, 2, 3, String::from(";http://y")),
+            ]
+        );
+
+        // The overlay cuts a run in two
+        let hole = Rect::new(8, 0, 1, 5);
+        let widths: Vec<_> = runs(parser.screen(), &links, area, Some(hole))
+            .iter()
+            .map(|run| (run.x, run.width))
+            .collect();
+        assert_eq!(widths, [(5, 2), (7, 1), (9, 1)]);
+    }
+
+    #[test]
+    fn untags_the_frame() {
+        let mut parser = Parser::new(1, 10, 0);
+        let mut links = Links::default();
+        print(&mut parser, &mut links, "http://x", "é中");
+
+        let area = Rect::new(0, 0, 10, 1);
+        let mut buf = Buffer::empty(area);
+        tui_term::widget::PseudoTerminal::new(parser.screen()).render(area, &mut buf);
+        assert!(links::is_tagged(buf[(0, 0)].symbol()));
+
+        untag(&mut buf, area);
+        assert_eq!(buf[(0, 0)].symbol(), "é");
+        assert_eq!(buf[(1, 0)].symbol(), "中");
+    }
+}
@@ -1,2 +1,4 @@
+pub mod images;
+pub mod links;
 pub mod owner;
 pub mod tenant;
@@ -4,35 +4,42 @@ use ratatui::{
     Frame, Terminal,
     backend::{Backend, CrosstermBackend},
     layout::Rect,
+    style::{Color, Modifier, Style},
+    text::Line,
     widgets::{Block, Borders},
 };
+use regex::Regex;
 
 use bytes::Bytes;
 
 use std::{
-    io::{self, BufWriter, Read, Write},
-    sync::{
-        Arc, RwLock,
-        atomic::{AtomicBool, Ordering},
-    },
+    collections::HashSet,
+    io::{self, BufWriter, Write},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
 };
 
 use crossterm::{
-    cursor::MoveTo,
+    cursor::{MoveTo, SetCursorStyle},
     event::{
-        DisableMouseCapture, EnableMouseCapture, Event, MouseButton, MouseEventKind, poll, read,
+        DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste,
+        EnableFocusChange, EnableMouseCapture, Event, EventStream, KeyEvent, KeyEventKind,
+        KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
     },
     execute, queue,
-    style::ResetColor,
+    style::{Print, ResetColor},
     terminal::{
-        Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode,
-        enable_raw_mode,
+        BeginSynchronizedUpdate, Clear, ClearType, EndSynchronizedUpdate, EnterAlternateScreen,
+        LeaveAlternateScreen, SetTitle, disable_raw_mode, enable_raw_mode,
+        supports_keyboard_enhancement,
     },
 };
 
+use futures::StreamExt;
 use tokio::{
     sync::mpsc::{Receiver, Sender, channel},
     task::{self},
+    time::sleep_until,
 };
 
 use tui_term::widget::PseudoTerminal;
@@ -43,53 +50,109 @@ pub struct Size {
     rows: u16,
 }
 
+use crate::app::copy::hints::{self, HintMode, HintOutcome};
+use crate::app::copy::history::History;
+use crate::app::copy::mouse::MouseSelect;
+use crate::app::copy::search::{Search, SearchOutcome};
+use crate::app::copy::selection;
+use crate::app::copy::{CopyMode, CopyOutcome, Pane, clipboard};
+use crate::app::input::encoder::KeyEncoder;
 use crate::app::input::keyboard::handle_keyboard_input;
-use crate::app::input::mouse::handle_mouse;
+use crate::app::input::keymap::{Action, Keymap};
+use crate::app::input::mouse::{handle_mouse, handle_owner_mouse};
+use crate::app::input::paste::{handle_paste, write_chunked};
 use crate::app::lease::Lease;
+use crate::app::scroll::{self, View};
+use crate::app::tap::{PtyState, Tap, gate::Gate, host};
+use crate::config::{BellPolicy, Config};
 use crate::constants::*;
 
-use super::tenant::Overlay;
+use super::images::{self, Placement};
+use super::links::{self, LinkRun};
+use super::tenant::{Overlay, place_cursor};
 
 pub struct Container {
     pub rect: Rect,
     pub size: Size,
     pub parser: Arc<RwLock<vt100::Parser>>,
+    pub state: Arc<RwLock<PtyState>>,
     pub tx: Sender<Bytes>,
     pub rx: Option<Receiver<Bytes>>,
     pub status_tx: Sender<bool>,
     pub status_rx: Option<Receiver<bool>>,
     pub lease: Lease,
-    pub mouse_mode_enabled: Arc<AtomicBool>,
+    pub resize_tx: Option<Sender<(u16, u16)>>,
+    pub keymap: Keymap,
+    pub copy: Option<CopyMode>,
+    pub search: Option<Search>,
+    pub hints: Option<HintMode>,
+    pub hint_patterns: Vec<Regex>,
+    pub select: MouseSelect,
+    // Last title put on the host
+    pub title: String,
+    // A visual bell from the owner reverses it until then
+    pub flash_until: Option<Instant>,
+    // Last DECSCUSR shape put on the host
+    pub cursor_shape: u8,
+    // Whether the host window has focus, and what each pane was last told
+    pub host_focused: bool,
+    pub focus: (bool, bool),
+    // Images on the host after the last frame, and the kitty ones it holds
+    pub placed: Vec<Placement>,
+    pub sent_images: HashSet<u32>,
+    // Paces the PTY readers against the frames and keys
+    pub gate: Arc<Gate>,
+    pub config: Arc<Config>,
 }
 
 impl Container {
-    pub fn new() -> Self {
+    pub fn new(config: Arc<Config>) -> Self {
         let (cols, rows) = crossterm::terminal::size().unwrap_or((DEFAULT_WIDTH, DEFAULT_HEIGHT));
 
         let rect = Rect::new(0, 0, cols, rows);
 
-        // FIX: we want to scroll back to start of the owner
-        let parser = Arc::new(RwLock::new(vt100::Parser::new(rows, cols, 0)));
+        let parser = Arc::new(RwLock::new(vt100::Parser::new(
+            rows,
+            cols,
+            config.scrollback.owner,
+        )));
 
         // Create channels for PTY status
         let (tx, rx) = channel::<Bytes>(32);
         let (pty_status_tx, pty_status_rx) = channel::<bool>(1);
 
-        let lease = Lease::new();
-        let mouse_mode_enabled = Arc::new(AtomicBool::new(false));
-        let container = Self {
+        let gate = Arc::new(Gate::default());
+        let lease = Lease::new(config.clone(), gate.clone());
+        // The keymap was already checked when the config was loaded
+        let keymap = Keymap::new(&config.keys).expect("invalid keymap");
+        let hint_patterns = config.hints.regexes().expect("invalid hint patterns");
+        Self {
             rect,
             parser,
+            state: Arc::new(RwLock::new(PtyState::default())),
             size: Size { cols, rows },
             tx,
             rx: Some(rx),
             status_tx: pty_status_tx,
             status_rx: Some(pty_status_rx),
             lease,
-            mouse_mode_enabled,
-        };
-
-        container
+            resize_tx: None,
+            keymap,
+            copy: None,
+            search: None,
+            hints: None,
+            hint_patterns,
+            select: MouseSelect::default(),
+            title: String::new(),
+            flash_until: None,
+            cursor_shape: 0,
+            host_focused: true,
+            focus: (true, false),
+            placed: Vec::new(),
+            sent_images: HashSet::new(),
+            gate,
+            config,
+        }
     }
 
     pub async fn init_tenant(&mut self) -> Result<(), anyhow::Error> {
@@ -118,7 +181,7 @@ impl Container {
             pixel_width: 0,
         }) {
             Ok(pair) => pair,
-            Err(e) => return Err(e.into()),
+            Err(e) => return Err(e),
         };
 
         enable_raw_mode()?;
@@ -130,7 +193,7 @@ impl Container {
         let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string());
         let mut cmd = CommandBuilder::new(shell);
         let cwd = std::env::current_dir().unwrap();
-        cmd.args(&["-y", "-i", "--login"]);
+        cmd.args(["-y", "-i", "--login"]);
         cmd.cwd(cwd);
         cmd.env("TERM", "xterm-256color");
 
@@ -164,109 +227,75 @@ impl Container {
         });
 
         let mut writer = BufWriter::new(master.take_writer().unwrap());
-        let mut reader = master.try_clone_reader().unwrap();
+        let reader = master.try_clone_reader().unwrap();
+
+        // Create a channel for resize operations, the master lives with it
+        let (resize_tx, mut resize_rx) = channel::<(u16, u16)>(10);
+        self.resize_tx = Some(resize_tx);
+        let resize_status_tx = self.status_tx.clone();
+
+        task::spawn_blocking(move || {
+            let rt = tokio::runtime::Handle::current();
+            rt.block_on(async {
+                while let Some((rows, cols)) = resize_rx.recv().await {
+                    if let Err(e) = master.resize(PtySize {
+                        rows,
+                        cols,
+                        pixel_height: 0,
+                        pixel_width: 0,
+                    }) {
+                        eprintln!("Failed to resize PTY: {}", e);
+                        let _ = resize_status_tx.send(true).await;
+                        break;
+                    }
+                }
+            });
+            drop(master);
+        });
 
         // Clone status sender for the reader task
         let reader_status_tx = self.status_tx.clone();
 
         {
-            let parser = self.parser.clone();
-            let mouse_tracker = self.mouse_mode_enabled.clone();
-
-            task::spawn_blocking(move || {
-                let mut buf = [0u8; 8192];
-                // TODO: magic number?
-                let mut processed_buf = Vec::new();
-                loop {
-                    // Handle read errors or EOF
-                    let size = match reader.read(&mut buf) {
-                        Ok(0) => {
-                            // EOF detected - terminal process ended
-                            let rt = tokio::runtime::Handle::current();
-                            rt.block_on(async {
-                                let _ = reader_status_tx.send(true).await;
-                            });
-                            break;
-                        }
-                        Ok(size) => size,
-                        Err(e) => {
-                            eprintln!("Read error: {}", e);
-                            // Signal error
-                            let rt = tokio::runtime::Handle::current();
-                            rt.block_on(async {
-                                let _ = reader_status_tx.send(true).await;
-                            });
-                            break;
-                        }
-                    };
-
-                    if size > 0 {
-                        processed_buf.extend_from_slice(&buf[..size]);
-
-                        let data_str = String::from_utf8_lossy(&processed_buf);
-
-                        // Check for mouse mode ENABLE sequences (more comprehensive)
-                        if data_str.contains("\x1b[?1000h") ||  // VT200 mouse tracking
-                        data_str.contains("\x1b[?1002h") ||  // VT200 button event mouse tracking
-                        data_str.contains("\x1b[?1003h") ||  // VT200 any event mouse tracking  
-                        data_str.contains("\x1b[?1006h") ||  // SGR mouse mode
-                        data_str.contains("\x1b[?1015h") ||  // URXVT mouse mode
-                        data_str.contains("\x1b[?9h") ||     // X10 mouse tracking
-                        data_str.contains("\x1b[?1005h") ||  // UTF-8 mouse mode
-                        data_str.contains("\x1b[?1004h")
-                        {
-                            // Focus events (often used with mouse)
-                            mouse_tracker.store(true, Ordering::Relaxed);
-                        }
-
-                        // Check for mouse mode DISABLE sequences
-                        if data_str.contains("\x1b[?1000l")
-                            || data_str.contains("\x1b[?1002l")
-                            || data_str.contains("\x1b[?1003l")
-                            || data_str.contains("\x1b[?1006l")
-                            || data_str.contains("\x1b[?1015l")
-                            || data_str.contains("\x1b[?9l")
-                            || data_str.contains("\x1b[?1005l")
-                            || data_str.contains("\x1b[?1004l")
-                        {
-                            mouse_tracker.store(false, Ordering::Relaxed);
-                        }
+            let tap = Tap::new(
+                self.parser.clone(),
+                self.state.clone(),
+                self.tx.clone(),
+                self.gate.clone(),
+                self.config.clipboard.owner,
+            );
 
-                        // Additional check: Look for DECSET sequences that might indicate mouse capability
-                        if data_str.contains("\x1b[?47h") ||    // Alternate screen buffer (often used with mouse apps)
-                        data_str.contains("\x1b[?1047h") ||   // Alternate screen buffer
-                        data_str.contains("\x1b[?1049h")
-                        {
-                            // Alternate screen buffer + cursor save
-                            // Many mouse-capable apps use alternate screen, so enable mouse preemptively
-                            // but only if we're in a terminal that likely supports it
-                            if std::env::var("TERM").unwrap_or_default().contains("xterm")
-                                || std::env::var("TERM").unwrap_or_default().contains("screen")
-                            {
-                                mouse_tracker.store(true, Ordering::Relaxed);
-                            }
-                        }
-
-                        // Check for alternate screen disable (often means mouse apps are exiting)
-                        if data_str.contains("\x1b[?47l")
-                            || data_str.contains("\x1b[?1047l")
-                            || data_str.contains("\x1b[?1049l")
-                        {
-                            mouse_tracker.store(false, Ordering::Relaxed);
-                        }
-                        let mut parser = parser.write().unwrap();
-                        parser.process(&processed_buf);
-                        // Clear the processed portion of the buffer
-                        processed_buf.clear();
-                    }
-                }
-            });
+            task::spawn_blocking(move || tap.run(reader, reader_status_tx));
         }
 
         // Set up terminal
         let mut stdout = io::stdout();
         execute!(stdout, ResetColor)?;
-        execute!(stdout, EnableMouseCapture, EnterAlternateScreen,)?;
+        execute!(
+            stdout,
+            EnableMouseCapture,
+            EnableBracketedPaste,
+            EnableFocusChange,
+            EnterAlternateScreen,
+        )?;
+
+        // Let the host tell keys like Ctrl+I and Tab apart when it can, so
+        // kitty keyboard apps get what they asked for
+        let keyboard_enhanced = supports_keyboard_enhancement().unwrap_or(false);
+        if keyboard_enhanced {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(
+                    KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                        | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                )
+            )?;
+        }
+
+        // Save the host's title to put it back on the way out
+        if self.config.title.forward {
+            execute!(stdout, Print("\x1b[22;0t"))?;
+        }
 
         let backend = CrosstermBackend::new(stdout);
         let mut terminal = Terminal::new(backend)?;
@@ -277,18 +306,13 @@ impl Container {
         // Handle writing to PTY with error detection
         tokio::spawn(async move {
             while let Some(bytes) = rx.recv().await {
-                if let Err(e) = writer.write_all(&bytes) {
+                if let Err(e) = write_chunked(&mut writer, &bytes) {
                     eprintln!("Write error: {}", e);
                     break;
                 }
-                if let Err(e) = writer.flush() {
-                    eprintln!("Flush error: {}", e);
-                    break;
-                }
             }
             // Clean up resources
             drop(writer);
-            drop(master);
         });
 
         // Run the terminal UI with PTY status monitoring
@@ -300,30 +324,385 @@ impl Container {
         // TODO: kill tenant processes here
         //}
 
+        if keyboard_enhanced {
+            execute!(std::io::stdout(), PopKeyboardEnhancementFlags)?;
+        }
+        if self.config.title.forward {
+            execute!(std::io::stdout(), Print("\x1b[23;0t"))?;
+        }
+        execute!(std::io::stdout(), SetCursorStyle::DefaultUserShape)?;
+        images::forget(&mut std::io::stdout(), self.sent_images.drain())?;
         disable_raw_mode()?;
-        execute!(std::io::stdout(), DisableMouseCapture, LeaveAlternateScreen)?;
+        execute!(
+            std::io::stdout(),
+            DisableMouseCapture,
+            DisableBracketedPaste,
+            DisableFocusChange,
+            LeaveAlternateScreen
+        )?;
         Ok(())
     }
 
-    pub fn render(&mut self, f: &mut Frame, screen: &Screen) {
+    pub async fn resize(&mut self, cols: u16, rows: u16) {
+        self.rect = Rect::new(0, 0, cols, rows);
+        self.size = Size { cols, rows };
+
+        // Keep the owner parser and PTY in lockstep with the host
+        {
+            let mut parser = self.parser.write().unwrap();
+            parser.set_size(rows, cols);
+            self.state.write().unwrap().history = scroll::history_len(&mut parser);
+        }
+        if let Some(resize_tx) = &self.resize_tx
+            && let Err(e) = resize_tx.try_send((rows, cols))
+        {
+            eprintln!("Failed to send resize command: {}", e);
+        }
+
+        // The host drops sixel pixels when it resizes
+        self.placed.clear();
+
+        // Pull the floating window back inside the new bounds
+        if self.lease.tenant.fit_to((cols, rows)) {
+            let rect = self.lease.tenant.rect;
+            self.lease.resize_screen(rect.height, rect.width).await;
+        }
+
+        if let Some(copy) = &mut self.copy {
+            let parser = match copy.pane {
+                Pane::Owner => &self.parser,
+                Pane::Tenant => &self.lease.tenant_parser,
+            };
+            copy.fit(&History::new(&mut parser.write().unwrap()));
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        f: &mut Frame,
+        (screen, view): (&Screen, View),
+        (tenant_screen, tenant_view): (&Screen, View),
+    ) {
+        let status = self.status(Pane::Owner, view);
         let block = Block::default().borders(Borders::NONE);
-        let pseudo_term_owner = PseudoTerminal::new(screen).block(block.clone()).cursor(
-            tui_term::widget::Cursor::default()
-                .visibility(!self.lease.tenant_visible)
-                .style(
-                    ratatui::style::Style::default()
-                        .add_modifier(ratatui::style::Modifier::RAPID_BLINK),
-                ),
-        );
+        let pseudo_term_owner = PseudoTerminal::new(screen)
+            .block(block.clone())
+            .cursor(tui_term::widget::Cursor::default().visibility(false));
 
         let inner = block.inner(self.rect);
         f.render_widget(pseudo_term_owner, inner);
         f.render_widget(block.clone(), inner);
+        images::blank(f.buffer_mut(), inner);
+        links::untag(f.buffer_mut(), inner);
+
+        self.render_copy(f, Pane::Owner, inner, view.top());
+        if !self.lease.tenant_visible && status.is_none() {
+            place_cursor(f, screen, inner);
+        }
+
+        // The owner has no border, so its status sits top right. A visual
+        // bell flashes it the way the tenant's border flashes, showing
+        // "[bell]" when there's no status to flash.
+        let flash = self.flash_until.is_some_and(|until| Instant::now() < until);
+        let reversed = status.is_some() != flash;
+        if let Some(status) = status.or_else(|| flash.then(|| String::from("[bell]"))) {
+            let mut style = Style::default();
+            if reversed {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            let indicator = Line::from(status).style(style);
+            let width = (indicator.width() as u16).min(inner.width);
+            let area = Rect::new(inner.right() - width, inner.y, width, 1.min(inner.height));
+            f.render_widget(indicator, area);
+        }
+
         if self.lease.tenant_visible && self.tenant_running() {
-            self.lease
-                .tenant
-                .render(f, self.lease.tenant_parser.read().unwrap().screen());
+            let status = self.status(Pane::Tenant, tenant_view);
+            self.lease.tenant.render(f, tenant_screen, status);
+
+            let content = self.lease.tenant.content_rect().intersection(f.area());
+            self.render_copy(f, Pane::Tenant, content, tenant_view.top());
+        }
+    }
+
+    // A search prompt takes the place of the scroll position
+    fn status(&self, pane: Pane, view: View) -> Option<String> {
+        if let Some(search) = self.search.as_ref().filter(|search| search.pane == pane) {
+            return Some(search.prompt());
+        }
+        if self.hints.as_ref().is_some_and(|hints| hints.pane == pane) {
+            return view.status(Some("hint"));
+        }
+        view.status(self.copy_label(pane))
+    }
+
+    fn copy_label(&self, pane: Pane) -> Option<&'static str> {
+        self.copy
+            .as_ref()
+            .filter(|copy| copy.pane == pane)
+            .map(|copy| copy.label())
+    }
+
+    // Draws search matches, hints, selections and the copy mode cursor over
+    // the pane they belong to
+    fn render_copy(&self, f: &mut Frame, pane: Pane, area: Rect, top: usize) {
+        if let Some(search) = self.search.as_ref().filter(|search| search.pane == pane) {
+            let current = search.current();
+            for &bounds in search.matches() {
+                let style = if Some(bounds) == current {
+                    Style::default().bg(Color::LightRed).fg(Color::Black)
+                } else {
+                    Style::default().bg(Color::Yellow).fg(Color::Black)
+                };
+                selection::paint(f.buffer_mut(), area, top, bounds, |cell| {
+                    cell.set_style(style);
+                });
+            }
+        }
+
+        if let Some(hints) = self.hints.as_ref().filter(|hints| hints.pane == pane) {
+            hints.draw(f.buffer_mut(), area, top);
+        }
+
+        if let Some(selection) = self.select.selection(pane) {
+            selection::highlight(f.buffer_mut(), area, top, selection);
+        }
+
+        let Some(copy) = self.copy.as_ref().filter(|copy| copy.pane == pane) else {
+            return;
+        };
+        if let Some(selection) = &copy.selection {
+            selection::highlight(f.buffer_mut(), area, top, selection);
+        }
+        selection::mark(f.buffer_mut(), area, top, copy.cursor);
+    }
+
+    fn pane_parser(&self, pane: Pane) -> Arc<RwLock<vt100::Parser>> {
+        match pane {
+            Pane::Owner => self.parser.clone(),
+            Pane::Tenant => self.lease.tenant_parser.clone(),
+        }
+    }
+
+    fn handle_copy_key(&mut self, key_event: KeyEvent) {
+        let Some(mut copy) = self.copy.take() else {
+            return;
+        };
+
+        let parser = self.pane_parser(copy.pane);
+        let outcome = match key_event.kind {
+            KeyEventKind::Release => CopyOutcome::Stay,
+            _ => copy.handle_key(&key_event, &mut parser.write().unwrap()),
+        };
+        match outcome {
+            CopyOutcome::Stay => {
+                self.copy = Some(copy);
+                return;
+            }
+            CopyOutcome::Exit => {}
+            CopyOutcome::Yank(text) => clipboard::copy(&text),
+        }
+
+        scroll::to_live(&parser);
+    }
+
+    fn handle_search_key(&mut self, key_event: KeyEvent) {
+        let Some(mut search) = self.search.take() else {
+            return;
+        };
+
+        let parser = self.pane_parser(search.pane);
+        let outcome = match key_event.kind {
+            KeyEventKind::Release => SearchOutcome::Stay,
+            _ => search.handle_key(&key_event, &mut parser.write().unwrap()),
+        };
+        match outcome {
+            SearchOutcome::Stay => self.search = Some(search),
+            SearchOutcome::Exit => scroll::to_live(&parser),
+        }
+    }
+
+    fn handle_hint_key(&mut self, key_event: KeyEvent) {
+        let Some(mut hints) = self.hints.take() else {
+            return;
+        };
+
+        let outcome = match key_event.kind {
+            KeyEventKind::Release => HintOutcome::Stay,
+            _ => hints.handle_key(&key_event),
+        };
+        match outcome {
+            HintOutcome::Stay => self.hints = Some(hints),
+            HintOutcome::Exit => {}
+            HintOutcome::Copy(text) => clipboard::copy(&text),
+            HintOutcome::Open(text) => hints::open(&self.config.hints.opener, &text),
+        }
+    }
+
+    // Mirrors the owner's title on the host, led by the tenant's if asked
+    fn update_title<W: io::Write>(&mut self, out: &mut W) -> io::Result<()> {
+        if !self.config.title.forward {
+            return Ok(());
+        }
+
+        let owner = self.state.read().unwrap().title.clone().unwrap_or_default();
+        let tenant = (self.config.title.tenant_prefix && self.lease.tenant_visible)
+            .then(|| self.lease.tenant_state.read().unwrap().title.clone())
+            .flatten()
+            .filter(|title| !title.is_empty());
+        let title = match tenant {
+            Some(tenant) if owner.is_empty() => tenant,
+            Some(tenant) => format!("{} | {}", tenant, owner),
+            None => owner,
+        };
+
+        if title != self.title {
+            execute!(out, SetTitle(&title))?;
+            self.title = title;
+        }
+        Ok(())
+    }
+
+    // The host cursor takes the shape the focused pane asked for
+    fn update_cursor_shape<W: io::Write>(
+        &mut self,
+        out: &mut W,
+        focused: &RwLock<PtyState>,
+    ) -> io::Result<()> {
+        let shape = focused.read().unwrap().cursor_shape;
+        if shape == self.cursor_shape {
+            return Ok(());
+        }
+
+        let style = match shape {
+            1 => SetCursorStyle::BlinkingBlock,
+            2 => SetCursorStyle::SteadyBlock,
+            3 => SetCursorStyle::BlinkingUnderScore,
+            4 => SetCursorStyle::SteadyUnderScore,
+            5 => SetCursorStyle::BlinkingBar,
+            6 => SetCursorStyle::SteadyBar,
+            _ => SetCursorStyle::DefaultUserShape,
+        };
+        execute!(out, style)?;
+        self.cursor_shape = shape;
+        Ok(())
+    }
+
+    // Only the focused pane of a focused host has focus, so showing or hiding
+    // the overlay moves it between the two
+    fn report_focus(&mut self, pane: Pane) {
+        let focus = (
+            self.host_focused && pane == Pane::Owner,
+            self.host_focused && pane == Pane::Tenant,
+        );
+
+        if focus.0 != self.focus.0 {
+            send_focus(&self.state, &self.tx, focus.0);
+        }
+        if focus.1 != self.focus.1 {
+            send_focus(&self.lease.tenant_state, &self.lease.tenant_tx, focus.1);
+        }
+        self.focus = focus;
+    }
+
+    // OSC 8 links of both panes as they land on the host, the overlay
+    // covers the owner's
+    fn link_runs(&mut self, owner: &Screen, tenant: &Screen) -> Vec<LinkRun> {
+        let tenant_shown = self.lease.tenant_visible && self.tenant_running();
+        let hole = tenant_shown.then_some(self.lease.tenant.rect);
+        let mut runs = links::runs(owner, &self.state.read().unwrap().links, self.rect, hole);
+
+        if tenant_shown {
+            let content = self.lease.tenant.content_rect().intersection(self.rect);
+            let state = self.lease.tenant_state.read().unwrap();
+            runs.extend(links::runs(tenant, &state.links, content, None));
         }
+        runs
+    }
+
+    // Images of both panes as they land on the host, the owner's are
+    // hidden where the overlay covers them and the tenant's are clipped to it
+    fn image_placements(&mut self, owner: &Screen, tenant: &Screen) -> Vec<Placement> {
+        let tenant_shown = self.lease.tenant_visible && self.tenant_running();
+        let hole = tenant_shown.then_some(self.lease.tenant.rect);
+        let state = self.state.read().unwrap();
+        let mut placements = images::placements(owner, &state.images, self.rect, hole);
+
+        if tenant_shown {
+            let content = self.lease.tenant.content_rect().intersection(self.rect);
+            let state = self.lease.tenant_state.read().unwrap();
+            placements.extend(images::placements(tenant, &state.images, content, None));
+        }
+        placements
+    }
+
+    // Kitty images the panes have dropped are freed on the host too
+    fn forget_images<W: io::Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let owner = std::mem::take(&mut self.state.write().unwrap().images.evicted);
+        let tenant = std::mem::take(&mut self.lease.tenant_state.write().unwrap().images.evicted);
+        let sent = owner
+            .into_iter()
+            .chain(tenant)
+            .filter(|id| self.sent_images.remove(id));
+        images::forget(out, sent)
+    }
+
+    // Whether a pane on screen is in the middle of a synchronized update
+    fn synchronizing(&self) -> bool {
+        self.sync_deadline()
+            .is_some_and(|deadline| Instant::now() < deadline)
+    }
+
+    // When the updates the panes on screen started give up waiting
+    fn sync_deadline(&self) -> Option<Instant> {
+        let timeout = Duration::from_millis(SYNC_TIMEOUT_MS);
+        let deadline = |state: &RwLock<PtyState>| {
+            let since = state.read().unwrap().sync_since;
+            since.map(|since| since + timeout)
+        };
+        let tenant = self
+            .lease
+            .tenant_visible
+            .then(|| deadline(&self.lease.tenant_state))
+            .flatten();
+        deadline(&self.state).max(tenant)
+    }
+
+    // Copies apps inside made, sent between frames so they can't land in
+    // the middle of one
+    fn forward_clipboard(&mut self) {
+        let owner = std::mem::take(&mut self.state.write().unwrap().clipboard);
+        let tenant = std::mem::take(&mut self.lease.tenant_state.write().unwrap().clipboard);
+        for payload in owner.iter().chain(&tenant) {
+            clipboard::forward(payload);
+        }
+    }
+
+    fn ring_bells<W: io::Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let owner = std::mem::take(&mut self.state.write().unwrap().bell);
+        let tenant = std::mem::take(&mut self.lease.tenant_state.write().unwrap().bell);
+        let until = Instant::now() + Duration::from_millis(BELL_FLASH_MS);
+
+        let mut forward = false;
+        if owner {
+            match self.config.bell.owner {
+                BellPolicy::Forward => forward = true,
+                BellPolicy::Visual => self.flash_until = Some(until),
+                BellPolicy::Ignore => {}
+            }
+        }
+        if tenant {
+            match self.config.bell.tenant {
+                BellPolicy::Forward => forward = true,
+                BellPolicy::Visual => self.lease.tenant.flash_until = Some(until),
+                BellPolicy::Ignore => {}
+            }
+        }
+
+        if forward {
+            execute!(out, Print("\x07"))?;
+        }
+        Ok(())
     }
 
     pub async fn run<B: Backend + std::io::Write>(
@@ -338,115 +717,440 @@ impl Container {
         terminal.clear()?;
         terminal.flush()?;
 
+        let mut events = EventStream::new();
+        let gate = self.gate.clone();
+        let frame = Duration::from_millis(self.config.ui.frame_ms);
+        let mut last_frame = Instant::now()
+            .checked_sub(frame)
+            .unwrap_or_else(Instant::now);
+        let mut dirty = true;
+
+        // Keys typed while uncl asked the host for its colors
+        let typed = host::take_typed();
+        if !typed.is_empty() && self.tx.send(Bytes::from(typed)).await.is_err() {
+            eprintln!("Failed to send keys typed at startup");
+        }
+
         loop {
+            // The readers wait while the loop is busy, and run while it sleeps
+            let held = gate.hold();
+            if self.lease.expired() {
+                self.lease.tenant.cleanup(terminal)?;
+                self.lease = self.lease.renew();
+                self.init_tenant().await?;
+                enable_raw_mode()?;
+                execute!(stdout, EnableMouseCapture)?;
+                self.placed.clear();
+            }
+
             let mut sender: Sender<Bytes> = self.tx.clone();
+            let mut focused = parser.clone();
+            let mut focused_state = self.state.clone();
+            let mut pane = Pane::Owner;
 
             if self.lease.tenant_visible {
                 if self.tenant_running() {
                     sender = self.lease.tenant_tx.clone();
+                    focused = self.lease.tenant_parser.clone();
+                    focused_state = self.lease.tenant_state.clone();
+                    pane = Pane::Tenant;
                 } else {
                     // Important: If tenant is visible but not running, reset state
                     self.lease.tenant_visible = false;
                 }
             }
 
-            // Poll for terminal events with a short timeout
-            if poll(std::time::Duration::from_millis(0))? {
-                let (term_width, term_height) = crossterm::terminal::size()?;
+            // Copy mode ends when its pane loses focus
+            if self.copy.as_ref().is_some_and(|copy| copy.pane != pane) {
+                self.copy = None;
+            }
+            if self
+                .search
+                .as_ref()
+                .is_some_and(|search| search.pane != pane)
+            {
+                self.search = None;
+            }
+            if self.hints.as_ref().is_some_and(|hints| hints.pane != pane) {
+                self.hints = None;
+            }
 
-                match read()? {
-                    Event::Key(key_event) => {
-                        if handle_keyboard_input(
-                            &mut self.lease,
-                            &sender,
-                            key_event,
-                            (term_width, term_height),
-                        )
-                        .await
-                        {
-                            break;
-                        }
+            self.update_title(terminal.backend_mut())?;
+            self.ring_bells(terminal.backend_mut())?;
+            self.forward_clipboard();
+            self.update_cursor_shape(terminal.backend_mut(), &focused_state)?;
+            self.report_focus(pane);
+            self.forget_images(terminal.backend_mut())?;
+
+            // Draw when something changed, at most once per frame_ms
+            let next_frame = last_frame + frame;
+            if dirty && Instant::now() >= next_frame {
+                let tenant_parser = self.lease.tenant_parser.clone();
+
+                // Both screens stay locked from the check to the draw, so no
+                // update can start in between
+                let owner = parser.read().unwrap();
+                let tenant = tenant_parser.read().unwrap();
+                let view = scroll::view(&owner, &self.state.read().unwrap());
+                let tenant_view = scroll::view(&tenant, &self.lease.tenant_state.read().unwrap());
+                if !self.synchronizing() {
+                    let runs = self.link_runs(owner.screen(), tenant.screen());
+                    let placements = self.image_placements(owner.screen(), tenant.screen());
+                    let moved = placements != self.placed;
+                    queue!(terminal.backend_mut(), BeginSynchronizedUpdate)?;
+                    // A full redraw wipes sixel pixels that were left behind
+                    if moved {
+                        images::hide(terminal.backend_mut(), &self.placed, &placements)?;
+                        terminal.clear()?;
                     }
-                    Event::Mouse(m) => {
-                        if !self.lease.tenant_visible {
-                            // Only send mouse events if application has enabled mouse mode
-                            if self.mouse_mode_enabled.load(Ordering::Relaxed) {
-                                let (button_code, action) = match m.kind {
-                                    MouseEventKind::Down(MouseButton::Left) => (0, 'M'),
-                                    MouseEventKind::Up(MouseButton::Left) => (0, 'm'),
-                                    MouseEventKind::Down(MouseButton::Right) => (2, 'M'),
-                                    MouseEventKind::Up(MouseButton::Right) => (2, 'm'),
-                                    MouseEventKind::Down(MouseButton::Middle) => (1, 'M'),
-                                    MouseEventKind::Up(MouseButton::Middle) => (1, 'm'),
-                                    MouseEventKind::Drag(MouseButton::Left) => (32, 'M'),
-                                    MouseEventKind::Drag(MouseButton::Right) => (34, 'M'),
-                                    MouseEventKind::Drag(MouseButton::Middle) => (33, 'M'),
-                                    MouseEventKind::ScrollUp => (64, 'M'),
-                                    MouseEventKind::ScrollDown => (65, 'M'),
-                                    _ => (-1, ' '),
-                                };
-
-                                if button_code >= 0 {
-                                    let mouse_sequence = format!(
-                                        "\x1b[<{};{};{}{}",
-                                        button_code,
-                                        m.column + 1,
-                                        m.row + 1,
-                                        action
-                                    );
-
-                                    let bytes = Bytes::from(mouse_sequence.into_bytes());
-                                    if let Err(e) = sender.try_send(bytes) {
-                                        eprintln!("Failed to send mouse event: {}", e);
-                                    }
-                                }
-                            }
-                            // If mouse mode not enabled, ignore mouse events completely
-                        } else {
-                            handle_mouse(&mut self.lease, m, (term_width, term_height)).await;
-                        }
+                    let frame = terminal.draw(|f| {
+                        self.render(f, (owner.screen(), view), (tenant.screen(), tenant_view))
+                    })?;
+                    let buffer = (!runs.is_empty()).then(|| frame.buffer.clone());
+                    if let Some(buffer) = buffer {
+                        links::reprint(terminal.backend_mut(), &buffer, &runs)?;
                     }
-                    Event::FocusGained => {}
-                    Event::FocusLost => {}
-                    Event::Paste(_) => {}
-                    Event::Resize(cols, rows) => {
-                        //TODO: fix
-                        parser.write().unwrap().set_size(rows, cols);
-                        if self.lease.tenant_visible {
-                            self.lease
-                                .resize_screen(
-                                    self.lease.tenant.rect.height,
-                                    self.lease.tenant.rect.width,
-                                )
-                                .await;
-                        }
+                    if moved {
+                        images::show(terminal.backend_mut(), &placements, &mut self.sent_images)?;
+                        self.placed = placements;
                     }
-                };
+                    execute!(terminal.backend_mut(), EndSynchronizedUpdate)?;
+                    dirty = false;
+                    last_frame = Instant::now();
+                }
             }
 
-            // Check if the PTY process has ended (non-blocking)
-            if let Ok(true) = pty_status_rx.try_recv() {
+            // Nothing to do until the host or a PTY says something, or a
+            // frame, a held update or a bell flash is due
+            let wake = [
+                dirty.then_some(next_frame),
+                dirty.then(|| self.sync_deadline()).flatten(),
+                self.flash_until,
+                self.lease.tenant.flash_until,
+            ]
+            .into_iter()
+            .flatten()
+            .filter(|&at| at > Instant::now())
+            .min();
+
+            drop(held);
+            let event = tokio::select! {
+                event = events.next() => event,
+                _ = gate.notified() => {
+                    dirty = true;
+                    continue;
+                }
+                _ = sleep_until(wake.unwrap_or_else(Instant::now).into()), if wake.is_some() => {
+                    dirty = true;
+                    continue;
+                }
+                // The shell underneath has ended
+                Some(true) = pty_status_rx.recv() => break,
+                Some(true) = self.lease.tenant_status_rx.recv() => {
+                    self.lease.tenant_visible = false;
+                    self.lease.tenant.is_dead = true;
+                    dirty = true;
+                    continue;
+                }
+            };
+            let Some(event) = event else {
                 break;
-            }
+            };
 
-            if let Ok(true) = self.lease.tenant_status_rx.try_recv() {
-                self.lease.tenant_visible = false;
-                self.lease.tenant.is_dead = true;
+            let _held = gate.hold();
+            dirty = true;
+            let (term_width, term_height) = crossterm::terminal::size()?;
+            match event? {
+                Event::Key(key_event) if self.copy.is_some() => {
+                    self.handle_copy_key(key_event);
+                }
+                Event::Key(key_event) if self.search.is_some() => {
+                    self.handle_search_key(key_event);
+                }
+                Event::Key(key_event) if self.hints.is_some() => {
+                    self.handle_hint_key(key_event);
+                }
+                Event::Key(key_event) => {
+                    self.select.clear();
+                    let encoder = KeyEncoder::new(
+                        focused.read().unwrap().screen(),
+                        focused_state.read().unwrap().kitty.flags(),
+                    );
+                    match handle_keyboard_input(
+                        &mut self.lease,
+                        &mut self.keymap,
+                        &encoder,
+                        &focused,
+                        &sender,
+                        key_event,
+                        (term_width, term_height),
+                    )
+                    .await
+                    {
+                        Some(Action::Quit) => break,
+                        Some(Action::CopyMode) => {
+                            let mut parser = focused.write().unwrap();
+                            self.copy = Some(CopyMode::new(pane, &mut parser));
+                        }
+                        Some(Action::Search) => {
+                            let mut parser = focused.write().unwrap();
+                            self.search = Some(Search::new(pane, &mut parser));
+                        }
+                        Some(Action::Hints) => {
+                            let mut parser = focused.write().unwrap();
+                            self.hints = Some(HintMode::new(
+                                pane,
+                                &mut parser,
+                                &self.hint_patterns,
+                                &self.config.hints.alphabet,
+                            ));
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Mouse(m) => {
+                    if self.lease.tenant_visible {
+                        handle_mouse(
+                            &mut self.lease,
+                            &mut self.select,
+                            m,
+                            (term_width, term_height),
+                        )
+                        .await;
+                    } else {
+                        handle_owner_mouse(
+                            &parser,
+                            &self.state,
+                            &mut self.select,
+                            &sender,
+                            self.rect,
+                            hints::opener(&self.config.links.opener),
+                            m,
+                        );
+                    }
+                }
+                Event::FocusGained => self.host_focused = true,
+                Event::FocusLost => self.host_focused = false,
+                Event::Paste(text) => {
+                    let bracketed = focused.read().unwrap().screen().bracketed_paste();
+                    handle_paste(&sender, bracketed, text);
+                }
+                Event::Resize(cols, rows) => {
+                    self.resize(cols, rows).await;
+                }
             }
+        }
 
-            if self.lease.expired() {
-                self.lease.tenant.cleanup(terminal)?;
-                self.lease = self.lease.renew();
-                self.init_tenant().await?;
-                enable_raw_mode()?;
-                execute!(stdout, EnableMouseCapture)?;
-            }
+        Ok(())
+    }
+}
+
+fn send_focus(state: &RwLock<PtyState>, sender: &Sender<Bytes>, focused: bool) {
+    if !state.read().unwrap().focus_reporting {
+        return;
+    }
+
+    let report: &'static [u8] = if focused { b"\x1b[I" } else { b"\x1b[O" };
+    if let Err(e) = sender.try_send(Bytes::from_static(report)) {
+        eprintln!("Failed to send focus report: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(config: Config) -> Container {
+        Container::new(Arc::new(config))
+    }
+
+    #[test]
+    fn holds_frames_while_a_shown_pane_synchronizes() {
+        let mut container = container(Config::default());
+        assert_eq!(container.sync_deadline(), None);
+        assert!(!container.synchronizing());
 
-            // Small sleep to prevent CPU spinning
-            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
-            terminal.draw(|f| self.render(f, parser.read().unwrap().screen()))?;
+        let timeout = Duration::from_millis(SYNC_TIMEOUT_MS);
+        let now = Instant::now();
+        container.lease.tenant_state.write().unwrap().sync_since = Some(now);
+        // A hidden tenant holds nothing up
+        assert_eq!(container.sync_deadline(), None);
+        container.lease.tenant_visible = true;
+        assert_eq!(container.sync_deadline(), Some(now + timeout));
+        assert!(container.synchronizing());
+
+        // The later deadline of the two counts
+        container.state.write().unwrap().sync_since = Some(now - timeout / 2);
+        assert_eq!(container.sync_deadline(), Some(now + timeout));
+
+        // An update that never ends is given up on
+        container.lease.tenant_state.write().unwrap().sync_since = None;
+        container.state.write().unwrap().sync_since = Some(now - timeout);
+        assert!(container.sync_deadline().is_some());
+        assert!(!container.synchronizing());
+    }
+
+    #[test]
+    fn reports_focus_to_the_panes_that_asked() {
+        let mut container = container(Config::default());
+        let mut owner = container.rx.take().unwrap();
+        let mut tenant = container.lease.tenant_rx.take().unwrap();
+        let mut reports = |container: &mut Container, pane| {
+            container.report_focus(pane);
+            let drain = |rx: &mut Receiver<Bytes>| {
+                let mut sent = Vec::new();
+                while let Ok(bytes) = rx.try_recv() {
+                    sent.push(String::from_utf8_lossy(&bytes).into_owned());
+                }
+                sent.concat()
+            };
+            (drain(&mut owner), drain(&mut tenant))
+        };
+        let sent = |owner: &str, tenant: &str| (owner.to_string(), tenant.to_string());
+
+        // Only the owner turned 1004 on
+        container.state.write().unwrap().focus_reporting = true;
+        assert_eq!(reports(&mut container, Pane::Owner), sent("", ""));
+        assert_eq!(reports(&mut container, Pane::Tenant), sent("\x1b[O", ""));
+
+        container
+            .lease
+            .tenant_state
+            .write()
+            .unwrap()
+            .focus_reporting = true;
+        assert_eq!(reports(&mut container, Pane::Tenant), sent("", ""));
+        assert_eq!(
+            reports(&mut container, Pane::Owner),
+            sent("\x1b[I", "\x1b[O")
+        );
+        assert_eq!(
+            reports(&mut container, Pane::Tenant),
+            sent("\x1b[O", "\x1b[I")
+        );
+
+        // The host losing focus takes it from whichever pane had it
+        container.host_focused = false;
+        assert_eq!(reports(&mut container, Pane::Tenant), sent("", "\x1b[O"));
+        assert_eq!(reports(&mut container, Pane::Owner), sent("", ""));
+        container.host_focused = true;
+        assert_eq!(reports(&mut container, Pane::Owner), sent("\x1b[I", ""));
+    }
+
+    #[test]
+    fn rings_bells_by_policy() {
+        use BellPolicy::*;
+        // Bell policies, then whether the host beeps and each pane flashes
+        let cases = [
+            ((Forward, Ignore), (true, false, false)),
+            ((Visual, Forward), (false, true, false)),
+            ((Ignore, Visual), (false, false, false)),
+        ];
+        for ((owner, tenant), expected) in cases {
+            let mut config = Config::default();
+            config.bell.owner = owner;
+            config.bell.tenant = tenant;
+            let mut container = container(config);
+
+            // Only the owner rang
+            container.state.write().unwrap().bell = true;
+            let mut out = Vec::new();
+            container.ring_bells(&mut out).unwrap();
+            let found = (
+                out == b"\x07",
+                container.flash_until.is_some(),
+                container.lease.tenant.flash_until.is_some(),
+            );
+            assert_eq!(found, expected, "{:?}", (owner, tenant));
+            assert!(!container.state.read().unwrap().bell);
         }
 
-        Ok(())
+        // and the same for the tenant
+        let cases = [
+            (Forward, (true, false)),
+            (Visual, (false, true)),
+            (Ignore, (false, false)),
+        ];
+        for (tenant, expected) in cases {
+            let mut config = Config::default();
+            config.bell.owner = Forward;
+            config.bell.tenant = tenant;
+            let mut container = container(config);
+
+            container.lease.tenant_state.write().unwrap().bell = true;
+            let mut out = Vec::new();
+            container.ring_bells(&mut out).unwrap();
+            let found = (out == b"\x07", container.lease.tenant.flash_until.is_some());
+            assert_eq!(found, expected, "{:?}", tenant);
+            assert!(container.flash_until.is_none());
+        }
+    }
+
+    #[test]
+    fn titles_the_host_after_the_focused_pane() {
+        let mut config = Config::default();
+        config.title.tenant_prefix = true;
+        let mut container = container(config);
+        let title = |container: &mut Container| {
+            let mut out = Vec::new();
+            container.update_title(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        container.state.write().unwrap().title = Some("vim".to_string());
+        container.lease.tenant_state.write().unwrap().title = Some("htop".to_string());
+
+        assert_eq!(title(&mut container), "\x1b]0;vim\x07");
+        // Unchanged titles aren't sent again
+        assert_eq!(title(&mut container), "");
+        container.lease.tenant_visible = true;
+        assert_eq!(title(&mut container), "\x1b]0;htop | vim\x07");
+        container.state.write().unwrap().title = None;
+        assert_eq!(title(&mut container), "\x1b]0;htop\x07");
+        container.lease.tenant_visible = false;
+        assert_eq!(title(&mut container), "\x1b]0;\x07");
+
+        // Without the prefix the tenant never names the host
+        let mut container = self::container(Config::default());
+        container.state.write().unwrap().title = Some("vim".to_string());
+        container.lease.tenant_state.write().unwrap().title = Some("htop".to_string());
+        container.lease.tenant_visible = true;
+        assert_eq!(title(&mut container), "\x1b]0;vim\x07");
+
+        // and with forwarding off nothing is sent at all
+        let mut config = Config::default();
+        config.title.forward = false;
+        let mut container = self::container(config);
+        container.state.write().unwrap().title = Some("vim".to_string());
+        assert_eq!(title(&mut container), "");
+    }
+
+    #[test]
+    fn shapes_the_cursor_after_the_focused_pane() {
+        let mut container = container(Config::default());
+        let shape = |container: &mut Container, focused: &RwLock<PtyState>| {
+            let mut out = Vec::new();
+            container.update_cursor_shape(&mut out, focused).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let owner = container.state.clone();
+        let tenant = container.lease.tenant_state.clone();
+
+        // The host starts out with its own shape
+        assert_eq!(shape(&mut container, &owner), "");
+        tenant.write().unwrap().cursor_shape = 5;
+        assert_eq!(shape(&mut container, &tenant), "\x1b[5 q");
+        assert_eq!(shape(&mut container, &tenant), "");
+
+        // Focusing the owner puts the host's back, and the tenant's returns
+        // with its focus
+        assert_eq!(shape(&mut container, &owner), "\x1b[0 q");
+        assert_eq!(shape(&mut container, &tenant), "\x1b[5 q");
+
+        // An exited tenant's shape goes with it
+        container.lease = container.lease.renew();
+        let tenant = container.lease.tenant_state.clone();
+        assert_eq!(shape(&mut container, &tenant), "\x1b[0 q");
+        owner.write().unwrap().cursor_shape = 2;
+        assert_eq!(shape(&mut container, &owner), "\x1b[2 q");
     }
 }
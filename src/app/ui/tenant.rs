@@ -4,13 +4,14 @@ use portable_pty::{CommandBuilder, PtySize, native_pty_system};
 use ratatui::{
     Frame, Terminal,
     backend::Backend,
-    layout::Rect,
+    layout::{Margin, Rect},
     prelude::CrosstermBackend,
-    style::{Color, Style},
+    style::{Color, Modifier, Style},
+    text::Line,
     widgets::{Block, Borders, block::Position},
 };
 
-use std::io::{BufWriter, Read, Write};
+use std::{io::BufWriter, sync::Arc, time::Instant};
 
 use crossterm::{
     cursor::MoveTo,
@@ -25,11 +26,14 @@ use tokio::task::{self};
 use tui_term::widget::PseudoTerminal;
 use vt100::Screen;
 
+use crate::app::input::paste::write_chunked;
 use crate::app::lease::Lease;
+use crate::app::tap::Tap;
+use crate::config::Config;
 
-use crate::constants::{
-    DEFAULT_HEIGHT, DEFAULT_WIDTH, DEFAULT_X, DEFAULT_Y, MIN_HEIGHT, MIN_WIDTH, ResizeDirection,
-};
+use crate::constants::{OVERLAY_INSET, ResizeDirection};
+
+use super::{images, links};
 
 pub struct Size {
     cols: u16,
@@ -44,37 +48,46 @@ pub struct Overlay {
     pub resize_direction: Option<ResizeDirection>,
     pub size: Size,
     pub is_dead: bool,
+    pub restore_rect: Option<Rect>,
+    // A press inside the content went to the tenant, so does the rest of it
+    pub mouse_grabbed: bool,
+    // A visual bell lights up the border until then
+    pub flash_until: Option<Instant>,
+    pub config: Arc<Config>,
 }
 
 impl Overlay {
-    pub fn new() -> Self {
-        let overlay = Self {
-            rect: Rect::new(DEFAULT_X, DEFAULT_Y, DEFAULT_WIDTH, DEFAULT_HEIGHT),
+    pub fn new(config: Arc<Config>) -> Self {
+        let window = &config.window;
+        Self {
+            rect: Rect::new(window.x, window.y, window.width, window.height),
             dragging: false,
             drag_offset: (0, 0),
             resizing: false,
             resize_direction: None,
             size: Size {
-                cols: DEFAULT_WIDTH,
-                rows: DEFAULT_HEIGHT,
+                cols: window.width,
+                rows: window.height,
             },
             is_dead: true,
-        };
-
-        overlay
+            restore_rect: None,
+            mouse_grabbed: false,
+            flash_until: None,
+            config,
+        }
     }
 
     pub async fn initialize_pty(&mut self, lease: &mut Lease) -> Result<(), anyhow::Error> {
         let pty_system = native_pty_system();
         //Create pty pair
         let pair = match pty_system.openpty(PtySize {
-            rows: self.size.rows - 4,
-            cols: self.size.cols - 4,
+            rows: self.size.rows - OVERLAY_INSET,
+            cols: self.size.cols - OVERLAY_INSET,
             pixel_height: 0,
             pixel_width: 0,
         }) {
             Ok(pair) => pair,
-            Err(e) => return Err(e.into()),
+            Err(e) => return Err(e),
         };
 
         //Get pty master/slave
@@ -97,15 +110,15 @@ impl Overlay {
         let resize_status_tx = lease.tenant_status_tx.clone();
 
         let mut writer = BufWriter::new(master.take_writer().unwrap());
-        let mut reader = master.try_clone_reader().unwrap();
+        let reader = master.try_clone_reader().unwrap();
 
         task::spawn_blocking(move || {
             let rt = tokio::runtime::Handle::current();
             rt.block_on(async {
                 while let Some((rows, cols)) = resize_rx.recv().await {
                     if let Err(e) = master.resize(PtySize {
-                        rows: rows - 4,
-                        cols: cols - 4,
+                        rows,
+                        cols,
                         pixel_height: 0,
                         pixel_width: 0,
                     }) {
@@ -120,8 +133,13 @@ impl Overlay {
         });
 
         //Spawn the shell in pty and monitor for exit
+        let child = slave.spawn_command(cmd);
+        if let Ok(child) = &child {
+            lease.set_killer(child.clone_killer());
+        }
+
         task::spawn_blocking(move || {
-            let mut child = match slave.spawn_command(cmd) {
+            let mut child = match child {
                 Ok(child) => child,
                 Err(e) => {
                     eprintln!("Failed to spawn command: {}", e);
@@ -148,44 +166,14 @@ impl Overlay {
         // Clone status sender for the reader task
         let reader_status_tx = lease.tenant_status_tx.clone();
         {
-            let parser = lease.tenant_parser.clone();
-            task::spawn_blocking(move || {
-                let mut buf = [0u8; 8192];
-                // TODO: magic number?
-
-                let mut processed_buf = Vec::new();
-                loop {
-                    // Handle read errors or EOF
-                    let size = match reader.read(&mut buf) {
-                        Ok(0) => {
-                            // EOF detected - terminal process ended
-                            let rt = tokio::runtime::Handle::current();
-                            rt.block_on(async {
-                                let _ = reader_status_tx.send(true).await;
-                            });
-                            break;
-                        }
-                        Ok(size) => size,
-                        Err(e) => {
-                            eprintln!("Read error: {}", e);
-                            // Signal error
-                            let rt = tokio::runtime::Handle::current();
-                            rt.block_on(async {
-                                let _ = reader_status_tx.send(true).await;
-                            });
-                            break;
-                        }
-                    };
-
-                    if size > 0 {
-                        processed_buf.extend_from_slice(&buf[..size]);
-                        let mut parser = parser.write().unwrap();
-                        parser.process(&processed_buf);
-                        // Clear the processed portion of the buffer
-                        processed_buf.clear();
-                    }
-                }
-            });
+            let tap = Tap::new(
+                lease.tenant_parser.clone(),
+                lease.tenant_state.clone(),
+                lease.tenant_tx.clone(),
+                lease.gate.clone(),
+                lease.config.clipboard.tenant,
+            );
+            task::spawn_blocking(move || tap.run(reader, reader_status_tx));
         }
 
         // Set up terminal
@@ -210,14 +198,10 @@ impl Overlay {
         // Handle writing to PTY with error detection
         tokio::spawn(async move {
             while let Some(bytes) = rx.recv().await {
-                if let Err(e) = writer.write_all(&bytes) {
+                if let Err(e) = write_chunked(&mut writer, &bytes) {
                     eprintln!("Write error: {}", e);
                     break;
                 }
-                if let Err(e) = writer.flush() {
-                    eprintln!("Flush error: {}", e);
-                    break;
-                }
             }
         });
 
@@ -248,26 +232,47 @@ impl Overlay {
         Ok(())
     }
 
-    pub fn render(&mut self, f: &mut Frame, screen: &Screen) {
+    pub fn render(&mut self, f: &mut Frame, screen: &Screen, status: Option<String>) {
         let t = format!("uncl 0.1b | {}:{}", self.size.rows, self.size.cols);
-        let block = Block::default()
+        let mut border = Style::default().fg(self.config.border.color);
+        if self.flash_until.is_some_and(|until| Instant::now() < until) {
+            border = border.add_modifier(Modifier::REVERSED);
+        }
+        let mut block = Block::default()
             .borders(Borders::ALL)
             .title_position(Position::Bottom)
             .title_alignment(ratatui::layout::Alignment::Right)
-            .border_type(ratatui::widgets::BorderType::Rounded)
-            .border_style(Color::Green)
+            .border_type(self.config.border.kind)
+            .border_style(border)
             .title(t)
             .style(Style::default().bg(Color::Reset));
-        let pseudo_term = PseudoTerminal::new(screen).block(block.clone()).cursor(
-            tui_term::widget::Cursor::default().style(
-                ratatui::style::Style::default()
-                    .add_modifier(ratatui::style::Modifier::RAPID_BLINK),
-            ),
-        );
-
-        let inner = block.inner(self.rect);
+        if let Some(status) = &status {
+            block = block.title(Line::from(status.as_str()).left_aligned());
+        }
+
+        // The host's own cursor stands in, so it keeps the shape the app set
+        let pseudo_term = PseudoTerminal::new(screen)
+            .block(block.clone())
+            .cursor(tui_term::widget::Cursor::default().visibility(false));
+
+        // The host may be smaller than the overlay's minimum size
+        let area = self.rect.intersection(f.area());
+        let inner = block.inner(area);
         f.render_widget(pseudo_term, inner);
         f.render_widget(block.clone(), inner);
+        images::blank(f.buffer_mut(), area);
+        links::untag(f.buffer_mut(), area);
+
+        // The cursor belongs to the live screen, not the history
+        if status.is_none() {
+            place_cursor(f, screen, self.content_rect().intersection(f.area()));
+        }
+    }
+
+    // Where the tenant screen is drawn, inside the border and padding
+    pub fn content_rect(&self) -> Rect {
+        self.rect
+            .inner(Margin::new(OVERLAY_INSET / 2, OVERLAY_INSET / 2))
     }
 
     pub fn resize_to(
@@ -279,22 +284,24 @@ impl Overlay {
         bounds: (u16, u16),
     ) {
         //FIX: there are more ways to resize than handled here
+        let (min_width, min_height) = (self.config.window.min_width, self.config.window.min_height);
+
         // Ignore any resize attempts that fall below the minimum constraints
-        if width < MIN_WIDTH || height < MIN_HEIGHT {
+        if width < min_width || height < min_height {
             return;
         }
 
         // Safeguard: Ensure x and y are within bounds (can't move beyond the bounds of the screen)
-        x = x.max(0).min(bounds.0.saturating_sub(1)); // Prevent x from exceeding bounds width
-        y = y.max(0).min(bounds.1.saturating_sub(1)); // Prevent y from exceeding bounds height
+        x = x.min(bounds.0.saturating_sub(1)); // Prevent x from exceeding bounds width
+        y = y.min(bounds.1.saturating_sub(1)); // Prevent y from exceeding bounds height
 
         // Calculate the max width and height that are available for resizing
         let max_width = bounds.0.saturating_sub(x);
         let max_height = bounds.1.saturating_sub(y);
 
         // Safeguard against overflow by ensuring we do not resize past bounds or minimum sizes
-        width = width.min(max_width).max(MIN_WIDTH);
-        height = height.min(max_height).max(MIN_HEIGHT);
+        width = width.min(max_width).max(min_width);
+        height = height.min(max_height).max(min_height);
 
         // Ensure the x and y positions are within bounds based on the new size
         // This ensures the new window does not go out of bounds when resizing
@@ -314,6 +321,46 @@ impl Overlay {
         self.size.rows = height;
     }
 
+    pub fn fit_to(&mut self, bounds: (u16, u16)) -> bool {
+        // Shrink to the new bounds first, then pull the window back on screen
+        let width = self
+            .rect
+            .width
+            .min(bounds.0)
+            .max(self.config.window.min_width);
+        let height = self
+            .rect
+            .height
+            .min(bounds.1)
+            .max(self.config.window.min_height);
+        let resized = width != self.rect.width || height != self.rect.height;
+
+        self.rect.width = width;
+        self.rect.height = height;
+        self.rect.x = self.rect.x.min(bounds.0.saturating_sub(width));
+        self.rect.y = self.rect.y.min(bounds.1.saturating_sub(height));
+        self.size.cols = width;
+        self.size.rows = height;
+
+        resized
+    }
+
+    pub fn toggle_maximized(&mut self, bounds: (u16, u16)) {
+        match self.restore_rect.take() {
+            Some(rect) => self.resize_to(rect.x, rect.y, rect.width, rect.height, bounds),
+            None => {
+                self.restore_rect = Some(self.rect);
+                self.resize_to(0, 0, bounds.0, bounds.1, bounds);
+            }
+        }
+    }
+
+    pub fn center(&mut self, bounds: (u16, u16)) {
+        let x = bounds.0.saturating_sub(self.rect.width) / 2;
+        let y = bounds.1.saturating_sub(self.rect.height) / 2;
+        self.move_to(x, y, bounds);
+    }
+
     pub fn move_to(&mut self, target_x: u16, target_y: u16, bounds: (u16, u16)) {
         let max_x = bounds.0.saturating_sub(self.rect.width);
         let max_y = bounds.1.saturating_sub(self.rect.height);
@@ -322,3 +369,64 @@ impl Overlay {
         self.rect.y = target_y.min(max_y);
     }
 }
+
+// Shows the host cursor where `screen` has it, unless the app hid it or it
+// falls outside `area`
+pub fn place_cursor(f: &mut Frame, screen: &Screen, area: Rect) {
+    if screen.hide_cursor() {
+        return;
+    }
+
+    let (row, col) = screen.cursor_position();
+    if row < area.height && col < area.width {
+        f.set_cursor_position((area.x + col, area.y + row));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlay(x: u16, y: u16) -> Overlay {
+        let mut config = Config::default();
+        config.window.x = x;
+        config.window.y = y;
+        config.window.width = 80;
+        config.window.height = 25;
+        config.window.min_width = 10;
+        config.window.min_height = 10;
+        Overlay::new(Arc::new(config))
+    }
+
+    #[test]
+    fn fits_to_the_screen() {
+        // Where it starts, the screen, then where it ends up and if it resized
+        let cases = [
+            ((10, 5), (200, 60), Rect::new(10, 5, 80, 25), false),
+            ((10, 5), (60, 20), Rect::new(0, 0, 60, 20), true),
+            ((10, 5), (70, 40), Rect::new(0, 5, 70, 25), true),
+            // Never smaller than the minimums, even past the screen
+            ((10, 5), (4, 3), Rect::new(0, 0, 10, 10), true),
+            // A window that still fits is only moved back on screen
+            ((150, 40), (120, 50), Rect::new(40, 25, 80, 25), false),
+            ((150, 40), (80, 25), Rect::new(0, 0, 80, 25), false),
+        ];
+        for ((x, y), bounds, rect, resized) in cases {
+            let mut overlay = overlay(x, y);
+            assert_eq!(overlay.fit_to(bounds), resized, "{:?}", bounds);
+            assert_eq!(overlay.rect, rect, "{:?}", bounds);
+            assert_eq!(
+                (overlay.size.cols, overlay.size.rows),
+                (rect.width, rect.height)
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_its_size_when_the_screen_grows_back() {
+        let mut overlay = overlay(10, 5);
+        assert!(overlay.fit_to((60, 20)));
+        assert!(!overlay.fit_to((200, 60)));
+        assert_eq!(overlay.rect, Rect::new(0, 0, 60, 20));
+    }
+}
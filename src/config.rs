@@ -0,0 +1,390 @@
+use std::{ffi::OsString, io::ErrorKind, path::PathBuf};
+
+use anyhow::{Context, Result, bail};
+use ratatui::{style::Color, widgets::BorderType};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+
+use crate::app::input::keymap::{Keymap, KeysConfig};
+use crate::constants::*;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub window: WindowConfig,
+    pub border: BorderConfig,
+    pub keys: KeysConfig,
+    pub ui: UiConfig,
+    pub scrollback: ScrollbackConfig,
+    pub hints: HintsConfig,
+    pub links: LinksConfig,
+    pub title: TitleConfig,
+    pub bell: BellConfig,
+    pub clipboard: ClipboardConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub width: u16,
+    pub height: u16,
+    pub x: u16,
+    pub y: u16,
+    pub min_width: u16,
+    pub min_height: u16,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            x: DEFAULT_X,
+            y: DEFAULT_Y,
+            min_width: MIN_WIDTH,
+            min_height: MIN_HEIGHT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BorderConfig {
+    #[serde(rename = "type", deserialize_with = "border_type")]
+    pub kind: BorderType,
+    #[serde(deserialize_with = "color")]
+    pub color: Color,
+}
+
+impl Default for BorderConfig {
+    fn default() -> Self {
+        Self {
+            kind: BorderType::Rounded,
+            color: Color::Green,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    // Shortest time between two frames in milliseconds, frames are only
+    // drawn when something changed
+    pub frame_ms: u64,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            frame_ms: DEFAULT_FRAME_MS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScrollbackConfig {
+    // Rows of history kept for the floating window and the shell under it
+    pub tenant: usize,
+    pub owner: usize,
+}
+
+impl Default for ScrollbackConfig {
+    fn default() -> Self {
+        Self {
+            tenant: DEFAULT_SCROLLBACK,
+            owner: DEFAULT_SCROLLBACK,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HintsConfig {
+    // Regexes picked out of the screen in hint mode
+    pub patterns: Vec<String>,
+    // Letters the labels are made of
+    pub alphabet: String,
+    // Command that capital labels hand the match to
+    pub opener: String,
+}
+
+impl Default for HintsConfig {
+    fn default() -> Self {
+        Self {
+            patterns: DEFAULT_HINT_PATTERNS
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            alphabet: DEFAULT_HINT_ALPHABET.to_string(),
+            opener: DEFAULT_OPENER.to_string(),
+        }
+    }
+}
+
+impl HintsConfig {
+    pub fn regexes(&self) -> Result<Vec<Regex>> {
+        self.patterns
+            .iter()
+            .map(|p| Regex::new(p).with_context(|| format!("invalid hint pattern `{}`", p)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinksConfig {
+    // Command that Ctrl+click hands an OSC 8 link's target to, an empty one
+    // leaves the click to the app
+    pub opener: String,
+}
+
+impl Default for LinksConfig {
+    fn default() -> Self {
+        Self {
+            opener: DEFAULT_OPENER.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TitleConfig {
+    // Put the owner's title on the host window or tab
+    pub forward: bool,
+    // Lead with the floating term's title while it's shown
+    pub tenant_prefix: bool,
+}
+
+impl Default for TitleConfig {
+    fn default() -> Self {
+        Self {
+            forward: true,
+            tenant_prefix: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BellPolicy {
+    // Ring the host's bell
+    Forward,
+    // Flash the pane that rang
+    Visual,
+    Ignore,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BellConfig {
+    pub tenant: BellPolicy,
+    pub owner: BellPolicy,
+}
+
+impl Default for BellConfig {
+    fn default() -> Self {
+        Self {
+            tenant: BellPolicy::Forward,
+            owner: BellPolicy::Forward,
+        }
+    }
+}
+
+// What an app inside may do with the host clipboard over OSC 52
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClipboardPolicy {
+    // Copy, and read back what was last copied through uncl
+    Allow,
+    Deny,
+    WriteOnly,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClipboardConfig {
+    pub tenant: ClipboardPolicy,
+    pub owner: ClipboardPolicy,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            tenant: ClipboardPolicy::WriteOnly,
+            owner: ClipboardPolicy::WriteOnly,
+        }
+    }
+}
+
+impl Config {
+    // $XDG_CONFIG_HOME/uncl/config.toml, falling back to ~/.config/uncl/config.toml
+    pub fn path() -> Option<PathBuf> {
+        config_path(
+            std::env::var_os("XDG_CONFIG_HOME"),
+            std::env::var_os("HOME"),
+        )
+    }
+
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        let raw = match std::fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            // No config file is fine, everything has a default
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+
+        Self::parse(&raw).with_context(|| format!("invalid config in {}", path.display()))
+    }
+
+    fn parse(raw: &str) -> Result<Self> {
+        let config: Config = toml::from_str(raw)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let w = &self.window;
+        if w.min_width <= OVERLAY_INSET || w.min_height <= OVERLAY_INSET {
+            bail!(
+                "window.min_width and window.min_height must be larger than {}",
+                OVERLAY_INSET
+            );
+        }
+        if w.width < w.min_width || w.height < w.min_height {
+            bail!(
+                "window size {}x{} is smaller than the minimum {}x{}",
+                w.width,
+                w.height,
+                w.min_width,
+                w.min_height
+            );
+        }
+        if self.ui.frame_ms == 0 {
+            bail!("ui.frame_ms must be at least 1");
+        }
+        Keymap::new(&self.keys)?;
+        self.hints.regexes()?;
+        // Capitals are kept for opening, so labels are lowercase only
+        let alphabet = &self.hints.alphabet;
+        if alphabet.chars().count() < 2 || !alphabet.chars().all(|c| c.is_ascii_lowercase()) {
+            bail!("hints.alphabet needs at least two lowercase letters");
+        }
+        if alphabet
+            .chars()
+            .enumerate()
+            .any(|(i, c)| alphabet[i + 1..].contains(c))
+        {
+            bail!("hints.alphabet has a letter more than once");
+        }
+        Ok(())
+    }
+}
+
+fn config_path(xdg_config_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    let base = xdg_config_home
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home.map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(base.join("uncl").join("config.toml"))
+}
+
+fn border_type<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BorderType, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    match s.to_lowercase().as_str() {
+        "plain" => Ok(BorderType::Plain),
+        "rounded" => Ok(BorderType::Rounded),
+        "double" => Ok(BorderType::Double),
+        "thick" => Ok(BorderType::Thick),
+        _ => Err(serde::de::Error::custom(format!(
+            "unknown border type `{}`, expected one of plain, rounded, double, thick",
+            s
+        ))),
+    }
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse()
+        .map_err(|_| serde::de::Error::custom(format!("unknown color `{}`", s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(raw: &str) -> String {
+        format!("{:#}", Config::parse(raw).unwrap_err())
+    }
+
+    #[test]
+    fn parses_a_minimal_file() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.window.width, DEFAULT_WIDTH);
+        assert_eq!(config.ui.frame_ms, DEFAULT_FRAME_MS);
+
+        let config = Config::parse("[window]\nwidth = 100\n[border]\ncolor = \"red\"").unwrap();
+        assert_eq!(config.window.width, 100);
+        assert_eq!(config.window.height, DEFAULT_HEIGHT);
+        assert_eq!(config.border.color, Color::Red);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(error("colour = \"red\"").contains("unknown field `colour`"));
+        assert!(error("[window]\nwidht = 100").contains("unknown field `widht`"));
+    }
+
+    #[test]
+    fn rejects_bad_colors_and_border_kinds() {
+        assert!(error("[border]\ncolor = \"reddish\"").contains("unknown color `reddish`"));
+        assert!(error("[border]\ntype = \"wavy\"").contains("unknown border type `wavy`"));
+        let config = Config::parse("[border]\ntype = \"Double\"\ncolor = \"#ff8800\"").unwrap();
+        assert_eq!(config.border.kind, BorderType::Double);
+        assert_eq!(config.border.color, Color::Rgb(0xff, 0x88, 0x00));
+    }
+
+    #[test]
+    fn rejects_a_window_below_its_minimum() {
+        let raw = "[window]\nwidth = 30\nmin_width = 40";
+        assert!(error(raw).contains("smaller than the minimum"));
+        assert!(error("[window]\nmin_height = 4").contains("must be larger than"));
+        assert!(Config::parse("[window]\nwidth = 40\nmin_width = 40").is_ok());
+    }
+
+    #[test]
+    fn keeps_the_openers_apart() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.links.opener, DEFAULT_OPENER);
+        assert_eq!(config.hints.opener, DEFAULT_OPENER);
+
+        let config = Config::parse("[links]\nopener = \"firefox --new-tab\"").unwrap();
+        assert_eq!(config.links.opener, "firefox --new-tab");
+        assert_eq!(config.hints.opener, DEFAULT_OPENER);
+        assert!(error("[links]\nbrowser = \"firefox\"").contains("unknown field `browser`"));
+    }
+
+    #[test]
+    fn resolves_the_path() {
+        let path = |xdg: Option<&str>, home: Option<&str>| {
+            config_path(xdg.map(OsString::from), home.map(OsString::from))
+        };
+        assert_eq!(
+            path(Some("/xdg"), Some("/home/me")),
+            Some(PathBuf::from("/xdg/uncl/config.toml"))
+        );
+        // An empty XDG_CONFIG_HOME counts as unset
+        assert_eq!(
+            path(Some(""), Some("/home/me")),
+            Some(PathBuf::from("/home/me/.config/uncl/config.toml"))
+        );
+        assert_eq!(path(None, None), None);
+    }
+}
@@ -13,3 +13,34 @@ pub const DEFAULT_HEIGHT: u16 = 25;
 
 pub const DEFAULT_X: u16 = 10;
 pub const DEFAULT_Y: u16 = 5;
+
+pub const DEFAULT_FRAME_MS: u64 = 16;
+
+pub const DEFAULT_SCROLLBACK: usize = 1000;
+
+// Longest a synchronized update may hold back drawing, in case the app
+// never ends it
+pub const SYNC_TIMEOUT_MS: u64 = 500;
+
+// How long a visual bell keeps a pane flashed
+pub const BELL_FLASH_MS: u64 = 150;
+
+// What hint mode picks out of the screen: URLs, paths, git hashes and IPv4
+// addresses with an optional port
+pub const DEFAULT_HINT_PATTERNS: &[&str] = &[
+    r#"\b(?:https?|ftp|file)://[^\s<>"'`]*[^\s<>"'`.,;:!?)\]]"#,
+    r"(?:~|\.\.?)?/?(?:[\w.@-]+/)+[\w.@-]+(?::\d+){0,2}",
+    r"\b[0-9a-f]{7,40}\b",
+    r"\b\d{1,3}(?:\.\d{1,3}){3}(?::\d+)?\b",
+];
+
+// Home row first, so the closest matches get the easiest labels
+pub const DEFAULT_HINT_ALPHABET: &str = "asdfghjklqwertyuiopzxcvbnm";
+
+#[cfg(target_os = "macos")]
+pub const DEFAULT_OPENER: &str = "open";
+#[cfg(not(target_os = "macos"))]
+pub const DEFAULT_OPENER: &str = "xdg-open";
+
+// Rows/columns taken up by the overlay border and padding on both sides
+pub const OVERLAY_INSET: u16 = 4;
@@ -1,10 +1,9 @@
 mod app;
+mod config;
 mod constants;
 use anyhow::Result;
-use tokio;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    app::run().await.unwrap();
-    Ok(())
+    app::run().await
 }